@@ -1,48 +1,1054 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use crate::config_check;
+use ::config::{Environment, File, FileFormat, Map, Source, Value, ValueKind};
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
 
-#[derive(Debug, Deserialize)]
+// Bundled with falog so that it works without any config file.
+pub const BUNDLED_CONFIG: &str = include_str!("../falog_default.yml");
+// Formats that aren't bundled, shown as a sample. Tests read it on top of the bundled config.
+#[cfg(test)]
+pub const SAMPLE_CONFIG: &str = include_str!("../falog_sample.yml");
+
+// The keys a config file can have at the top.
+pub const TOP_KEYS: &[&str] = &[
+    "include",
+    "formats",
+    "default_format",
+    "path_matches",
+    "stacktrace",
+    "theme",
+];
+
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub formats: HashMap<String, Format>,
     pub default_format: Option<String>,
     #[serde(default)]
     pub path_matches: HashMap<String, String>,
+    #[serde(default)]
+    pub stacktrace: Stacktrace,
+    #[serde(default)]
+    pub theme: Theme,
 }
 
+// A single config file. What it sets overrides what the files before it set, formats and
+// path matches by name. Formats are resolved once every file is read, as they can extend a
+// format of another file.
 #[derive(Debug, Deserialize)]
+struct Layer {
+    #[serde(default)]
+    formats: HashMap<String, Value>,
+    // Every other key, e.g. `stacktrace`.
+    #[serde(flatten)]
+    settings: Map<String, Value>,
+}
+
+impl Layer {
+    fn read(source: impl Source + Send + Sync + 'static) -> Result<Self, ::config::ConfigError> {
+        let layer: Self = ::config::Config::builder()
+            .add_source(source)
+            .build()?
+            .try_deserialize()?;
+        // Checked now rather than once merged, to tell which file is wrong.
+        Value::new(None, ValueKind::Table(layer.settings.clone())).try_deserialize::<Config>()?;
+        Ok(layer)
+    }
+}
+
+// The files a config file includes, e.g. `include: [formats/nginx.yml]`.
+#[derive(Debug, Default, Deserialize)]
+struct Includes {
+    #[serde(default)]
+    include: Vec<String>,
+}
+
+// Config keys set with `FALOG_` environment variables and `--set key=value`, applied in that
+// order after every config file. `FALOG_FORMATS__JSON__FIELDS__MESSAGE=text` is the same as
+// `--set formats.json.fields.message=text`. Values are text, or lists like `[a, b]` for keys
+// holding lists.
+// Unlike in files, they're merged key by key with what they override, formats too.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    // The keys set, nested like in a config file.
+    tree: Map<String, Value>,
+    // `FALOG_` variables that aren't config keys, e.g. FALOG_HOME. They're left out.
+    pub skipped: Vec<String>,
+}
+
+impl Overrides {
+    pub fn new(
+        vars: impl IntoIterator<Item = (OsString, OsString)>,
+        sets: &[String],
+    ) -> anyhow::Result<Self> {
+        let mut skipped = Vec::new();
+        let mut env = Map::new();
+        for (name, value) in vars {
+            let (Ok(name), Ok(value)) = (name.into_string(), value.into_string()) else {
+                continue;
+            };
+            let Some(key) = name.strip_prefix("FALOG_") else {
+                continue;
+            };
+            if name == "FALOG_CONFIG" {
+                continue;
+            }
+            match key_problem(&key.to_lowercase().replace("__", ".")) {
+                None => {
+                    env.insert(name, value);
+                }
+                Some(_) => skipped.push(name),
+            }
+        }
+        skipped.sort();
+
+        let mut builder = ::config::Config::builder().add_source(
+            Environment::with_prefix("FALOG")
+                .prefix_separator("_")
+                .separator("__")
+                .source(Some(env)),
+        );
+        for set in sets {
+            let (key, value) = set
+                .split_once('=')
+                .ok_or_else(|| anyhow!("--set `{}` isn't key=value", set))?;
+            if let Some(problem) = key_problem(key) {
+                bail!("--set {}: {}", set, problem);
+            }
+            builder = builder
+                .set_override(key, value)
+                .map_err(|e| anyhow!("--set {}: {}", set, e))?;
+        }
+        let tree = builder.build()?.try_deserialize::<Map<String, Value>>()?;
+        let tree = tree
+            .into_iter()
+            .map(|(key, value)| {
+                let value = with_lists(&[&key], value);
+                (key, value)
+            })
+            .collect();
+        Ok(Self { tree, skipped })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+}
+
+// Why the dotted `key` can't be overridden, if it can't: it isn't a key of config files, or
+// it's `include`, which only files can set.
+fn key_problem(key: &str) -> Option<String> {
+    let path: Vec<_> = key.split('.').collect();
+    if path[0] == "include" {
+        return Some("`include` can only be set in config files".to_string());
+    }
+    config_check::unknown_key(&path)
+}
+
+impl Config {
+    // The bundled config, overridden by every file of `files` and the files they include, and
+    // then by `overrides`.
+    pub fn load(explicit: Option<&Path>, overrides: &Overrides) -> anyhow::Result<Self> {
+        let mut layers = vec![Self::bundled_layer()];
+        for path in Self::files(explicit) {
+            for path in with_includes(&path)? {
+                let layer = Layer::read(File::from(path.as_path()).format(FileFormat::Yaml))
+                    .map_err(|e| anyhow!("config `{}`: {}", path.display(), e))?;
+                layers.push(layer);
+            }
+        }
+        Self::from_layers(layers, overrides)
+    }
+
+    #[cfg(test)]
+    pub fn bundled() -> Self {
+        Self::from_layers(vec![Self::bundled_layer()], &Overrides::default())
+            .expect("the bundled config is valid")
+    }
+
+    fn bundled_layer() -> Layer {
+        Layer::read(File::from_str(BUNDLED_CONFIG, FileFormat::Yaml))
+            .expect("the bundled config is valid")
+    }
+
+    #[cfg(test)]
+    pub fn sample() -> Self {
+        Self::from_layers(
+            vec![Self::bundled_layer(), Self::sample_layer()],
+            &Overrides::default(),
+        )
+        .expect("the sample config is valid")
+    }
+
+    #[cfg(test)]
+    fn sample_layer() -> Layer {
+        Layer::read(File::from_str(SAMPLE_CONFIG, FileFormat::Yaml))
+            .expect("the sample config is valid")
+    }
+
+    // Config files in the order they're applied: `/etc/falog/falog.yml`, then
+    // `$XDG_CONFIG_HOME/falog/falog.yml` (`~/.config` if not set), then the project's
+    // `falog.yml` in the current directory or the nearest parent having one. A file given with
+    // `--config` or `FALOG_CONFIG` is used instead of all of them.
+    pub fn files(explicit: Option<&Path>) -> Vec<PathBuf> {
+        if let Some(path) = explicit {
+            return vec![path.to_path_buf()];
+        }
+
+        let mut files = Self::search_paths(None);
+        files.retain(|path| path.is_file());
+        files.dedup();
+        files
+    }
+
+    // The paths `files` looks for config files at, whether they exist or not. For the project's
+    // `falog.yml`, those are the current directory and its parents up to the nearest having one.
+    pub fn search_paths(explicit: Option<&Path>) -> Vec<PathBuf> {
+        if let Some(path) = explicit {
+            return vec![path.to_path_buf()];
+        }
+
+        let mut paths = vec![PathBuf::from("/etc/falog/falog.yml")];
+        let user = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+        if let Some(user) = user {
+            paths.push(user.join("falog").join("falog.yml"));
+        }
+        if let Ok(dir) = env::current_dir() {
+            for dir in dir.ancestors() {
+                let path = dir.join("falog.yml");
+                let found = path.is_file();
+                paths.push(path);
+                if found {
+                    break;
+                }
+            }
+        }
+        paths
+    }
+
+    fn from_layers(layers: Vec<Layer>, overrides: &Overrides) -> anyhow::Result<Self> {
+        let mut formats = HashMap::new();
+        let mut settings = Map::new();
+        for layer in layers {
+            formats.extend(layer.formats);
+            for (key, value) in layer.settings {
+                // Path matches are replaced by name, anything else as a whole.
+                if key == "path_matches" {
+                    settings = merge(settings, Map::from([(key, value)]));
+                } else {
+                    settings.insert(key, value);
+                }
+            }
+        }
+
+        let mut tree = overrides.tree.clone();
+        if let Some(value) = tree.remove("formats") {
+            for (name, format) in value.into_table()? {
+                let format = match formats.remove(&name) {
+                    Some(base) => Value::new(
+                        None,
+                        ValueKind::Table(merge(base.into_table()?, format.into_table()?)),
+                    ),
+                    None => format,
+                };
+                formats.insert(name, format);
+            }
+        }
+        let mut config: Self =
+            Value::new(None, ValueKind::Table(merge(settings, tree))).try_deserialize()?;
+
+        for name in formats.keys() {
+            let format = resolve_format(name, &formats)?
+                .try_deserialize()
+                .map_err(|e| anyhow!("format `{}`: {}", name, e))?;
+            config.formats.insert(name.clone(), format);
+        }
+        Ok(config)
+    }
+}
+
+// Whether the key at `path` holds a list, which overrides write like `[a, b]`.
+fn is_list(path: &[&str]) -> bool {
+    matches!(
+        path,
+        ["formats", _, "columns"]
+            | ["formats", _, "fields", "exclude" | "include"]
+            | ["formats", _, "highlight", "disable"]
+            | ["stacktrace", "app_packages"]
+    )
+}
+
+// Text like `[a, b]` made a list, in `value` at `path` and the values it holds, where the key
+// holds a list. Anything else is kept as it is, e.g. a timestamp format like `[%d/%b/%Y]`.
+fn with_lists(path: &[&str], value: Value) -> Value {
+    match value.kind {
+        ValueKind::Table(table) => {
+            let table = table
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = with_lists(&[path, &[key.as_str()]].concat(), value);
+                    (key, value)
+                })
+                .collect();
+            Value::new(None, ValueKind::Table(table))
+        }
+        ValueKind::String(ref text) if is_list(path) => {
+            match text
+                .strip_prefix('[')
+                .and_then(|text| text.strip_suffix(']'))
+            {
+                Some(items) => {
+                    let items = items
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(Value::from)
+                        .collect::<Vec<_>>();
+                    Value::new(None, ValueKind::Array(items))
+                }
+                None => value,
+            }
+        }
+        _ => value,
+    }
+}
+
+// `path` and the files it includes, recursively, in the order they're applied: included files
+// first, so that what `path` sets overrides them. Included paths are relative to the file
+// including them.
+pub fn with_includes(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    add_includes(path, &mut Vec::new(), &mut files)?;
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+// `chain` and the first of `files` are canonical paths, to tell when two paths are the same file.
+fn add_includes(
+    path: &Path,
+    chain: &mut Vec<PathBuf>,
+    files: &mut Vec<(PathBuf, PathBuf)>,
+) -> anyhow::Result<()> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| anyhow!("config `{}`: {}", path.display(), e))?;
+    if chain.contains(&canonical) {
+        bail!(
+            "config `{}` includes itself through {}",
+            path.display(),
+            chain
+                .iter()
+                .map(|path| format!("`{}`", path.display()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    // A file included by more than one file is only read the first time.
+    if files.iter().any(|(file, _)| *file == canonical) {
+        return Ok(());
+    }
+
+    let includes: Includes = ::config::Config::builder()
+        .add_source(File::from(path).format(FileFormat::Yaml))
+        .build()
+        .and_then(|config| config.try_deserialize())
+        .map_err(|e| anyhow!("config `{}`: {}", path.display(), e))?;
+    chain.push(canonical.clone());
+    let dir = path.parent().unwrap_or(Path::new("."));
+    for include in includes.include {
+        add_includes(&dir.join(include), chain, files)?;
+    }
+    chain.pop();
+    files.push((canonical, path.to_path_buf()));
+    Ok(())
+}
+
+// The settings of a format, merged with the ones of the format it `extends`, recursively.
+// Mappings such as `fields` are merged key by key, anything else is replaced. `examples` aren't
+// inherited, as they seldom hold for a format with different settings.
+pub fn resolve_format(name: &str, formats: &HashMap<String, Value>) -> anyhow::Result<Value> {
+    let mut chain = Vec::new();
+    let mut name = name.to_string();
+    let mut resolved: Option<Map<String, Value>> = None;
+    loop {
+        let format = formats
+            .get(&name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "format `{}` extends `{}`, which doesn't exist",
+                    chain.last().unwrap(),
+                    name
+                )
+            })?
+            .clone()
+            .into_table()
+            .map_err(|e| anyhow!("format `{}`: {}", name, e))?;
+        chain.push(name.clone());
+
+        let mut format = format;
+        let base = format.remove("extends");
+        resolved = Some(match resolved {
+            Some(overrides) => {
+                format.remove("examples");
+                merge(format, overrides)
+            }
+            None => format,
+        });
+
+        match base {
+            Some(base) => {
+                name = base
+                    .into_string()
+                    .map_err(|e| anyhow!("format `{}`: extends: {}", chain.last().unwrap(), e))?;
+                if chain.contains(&name) {
+                    bail!(
+                        "format `{}` extends itself: {} -> {}",
+                        chain[0],
+                        chain.join(" -> "),
+                        name
+                    );
+                }
+            }
+            None => return Ok(Value::new(None, ValueKind::Table(resolved.unwrap()))),
+        }
+    }
+}
+
+fn merge(mut base: Map<String, Value>, overrides: Map<String, Value>) -> Map<String, Value> {
+    for (key, value) in overrides {
+        let merged = match (base.remove(&key), value) {
+            (
+                Some(Value {
+                    kind: ValueKind::Table(base),
+                    ..
+                }),
+                Value {
+                    kind: ValueKind::Table(overrides),
+                    ..
+                },
+            ) => Value::new(None, ValueKind::Table(merge(base, overrides))),
+            (_, value) => value,
+        };
+        base.insert(key, merged);
+    }
+    base
+}
+
+// Colors of the pretty and table outputs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Theme {
+    // A bundled theme: `dark`, `light` or `none`.
+    #[serde(default = "default_theme")]
+    pub name: String,
+    // Styles overriding the ones of the theme, by key, e.g. `level.error: bold red`.
+    // See `theme.rs` for the keys and `Style::parse` for the styles.
+    #[serde(default, deserialize_with = "dotted_keys")]
+    pub styles: HashMap<String, String>,
+}
+
+// Keys set with `--set` or FALOG_ variables are split at dots into tables, e.g.
+// `level: {error: red}`, which are joined back. Those win over a key written with dots.
+fn dotted_keys<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Entry {
+        Value(String),
+        Table(HashMap<String, Entry>),
+    }
+
+    fn flatten(prefix: &str, table: HashMap<String, Entry>, out: &mut Vec<(String, String)>) {
+        let mut tables = Vec::new();
+        for (key, entry) in table {
+            let key = match prefix {
+                "" => key,
+                _ => format!("{}.{}", prefix, key),
+            };
+            match entry {
+                Entry::Value(value) => out.push((key, value)),
+                Entry::Table(table) => tables.push((key, table)),
+            }
+        }
+        for (key, table) in tables {
+            flatten(&key, table, out);
+        }
+    }
+
+    let mut entries = Vec::new();
+    flatten("", HashMap::deserialize(deserializer)?, &mut entries);
+    Ok(entries.into_iter().collect())
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: default_theme(),
+            styles: HashMap::new(),
+        }
+    }
+}
+
+// How the `stacktrace` field of records is shown.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Stacktrace {
+    // Frames whose function or file starts with one of these are the application's own,
+    // e.g. `com.example.`, `/app/` or `myservice::`. When empty, every frame that doesn't look
+    // like it's from a standard or third party library is.
+    #[serde(default)]
+    pub app_packages: Vec<String>,
+    // Whether to fold runs of library frames and repeated frames into a single line.
+    #[serde(default = "default_true")]
+    pub collapse: bool,
+}
+
+impl Default for Stacktrace {
+    fn default() -> Self {
+        Self {
+            app_packages: Vec::new(),
+            collapse: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Format {
+    #[serde(flatten)]
+    pub format_type: FormatType,
+    // How records of this format are shown by the pretty output, e.g.
+    // `{ts:%H:%M:%S} {level:>5} {message} {rest}`. See `template.rs` for the syntax.
+    pub template: Option<String>,
+    #[serde(default)]
+    pub highlight: Highlight,
+    // Sample lines with the field values they should be read as. See `falog test-formats`.
+    #[serde(default)]
+    pub examples: Vec<Example>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Example {
+    pub line: String,
+    // Expected values by field name. `message`, `level`, `timestamp` (or `ts`) and
+    // `stacktrace` refer to the fields set in `fields`, like in filters.
+    #[serde(default, deserialize_with = "scalars")]
+    pub fields: BTreeMap<String, String>,
+}
+
+// What to highlight in messages of a format.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Highlight {
+    // Built-in rules to turn off: `url`, `uuid`, `ip`, `quoted`, `duration`, `http_method`,
+    // `http_status` and `number`.
+    #[serde(default)]
+    pub disable: Vec<String>,
+    // Highlighted before the built-in rules.
+    #[serde(default)]
+    pub rules: Vec<HighlightRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HighlightRule {
+    // A regex. If it has a capture named `token`, only that part is highlighted.
+    pub pattern: String,
+    // See `Style::parse`, e.g. `bold magenta`. The theme's `highlight.custom` when not set.
+    pub style: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type")]
-pub enum Format {
+#[allow(clippy::enum_variant_names)]
+pub enum FormatType {
     #[serde(rename = "json")]
-    JsonFormat { fields: Fields },
+    JsonFormat {
+        #[serde(default)]
+        fields: Fields,
+    },
     #[serde(rename = "regex")]
-    RegexFormat { format: String, fields: Fields },
+    RegexFormat {
+        format: String,
+        #[serde(default)]
+        fields: Fields,
+        payload: Option<Payload>,
+    },
+    // Kubernetes CRI log format. Fields: `time`, `stream` and `message`.
+    #[serde(rename = "cri")]
+    CriFormat {
+        #[serde(default = "Fields::cri", deserialize_with = "cri_fields")]
+        fields: Fields,
+    },
+    // Delimited values: CSV, TSV and W3C extended log files.
+    #[serde(rename = "csv")]
+    CsvFormat {
+        #[serde(default = "default_delimiter")]
+        delimiter: char,
+        #[serde(default = "default_quote")]
+        quote: char,
+        // Whether the first row holds the column names. Detected when not set.
+        header: Option<bool>,
+        columns: Option<Vec<String>>,
+        #[serde(default)]
+        fields: Fields,
+    },
+    // systemd journal, as written by `journalctl -o export` or `journalctl -o json`.
+    #[serde(rename = "journal")]
+    JournalFormat {
+        #[serde(default = "Fields::journal", deserialize_with = "journal_fields")]
+        fields: Fields,
+    },
+    // Docker json-file logging driver. Fields: `log`, `stream`, `time` and any `attrs`.
+    #[serde(rename = "docker")]
+    DockerFormat {
+        #[serde(default = "Fields::docker", deserialize_with = "docker_fields")]
+        fields: Fields,
+    },
 }
 
-#[derive(Debug, Deserialize)]
+impl FormatType {
+    pub fn fields(&self) -> &Fields {
+        match self {
+            Self::JsonFormat { fields }
+            | Self::RegexFormat { fields, .. }
+            | Self::CriFormat { fields }
+            | Self::CsvFormat { fields, .. }
+            | Self::JournalFormat { fields }
+            | Self::DockerFormat { fields } => fields,
+        }
+    }
+}
+
+// Lets expected values be written as numbers and booleans, e.g. `status: 200`.
+fn scalars<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Scalar {
+        Bool(bool),
+        Int(i64),
+        Float(f64),
+        String(String),
+    }
+
+    let values = BTreeMap::<String, Scalar>::deserialize(deserializer)?;
+    Ok(values
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Scalar::Bool(value) => value.to_string(),
+                Scalar::Int(value) => value.to_string(),
+                Scalar::Float(value) => value.to_string(),
+                Scalar::String(value) => value,
+            };
+            (name, value)
+        })
+        .collect())
+}
+
+fn default_theme() -> String {
+    "dark".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_quote() -> char {
+    '"'
+}
+
+// A named capture of a regex format that is further parsed, e.g. the JSON part of
+// `2024-01-01T00:00:00Z stdout F {"msg":"hi"}`. Parsed fields are merged into the record.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Payload {
+    pub capture: String,
+    #[serde(rename = "type")]
+    pub payload_type: PayloadType,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub enum PayloadType {
+    #[serde(rename = "json")]
+    Json,
+    #[serde(rename = "logfmt")]
+    Logfmt,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 #[serde(untagged)]
 pub enum Exclude {
     ExcludeOne(String),
     ExcludeMany(Vec<String>),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(untagged)]
 pub enum Include {
     IncludeOne(String),
     IncludeMany(Vec<String>),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct Fields {
     pub message: Option<String>,
+    pub level: Option<String>,
     pub timestamp: Option<TimestampField>,
     pub stacktrace: Option<String>,
     pub exclude: Option<Exclude>,
     pub include: Option<Include>,
+    #[serde(default)]
+    pub nested: Nested,
 }
 
-#[derive(Debug, Deserialize)]
+impl Fields {
+    // `exclude` as a list, and whether it's `exclude: all`.
+    pub fn excluded(&self) -> (bool, Vec<String>) {
+        match &self.exclude {
+            Some(Exclude::ExcludeOne(one)) if one == "all" => (true, Vec::new()),
+            Some(Exclude::ExcludeOne(one)) => (false, vec![one.clone()]),
+            Some(Exclude::ExcludeMany(many)) => (false, many.clone()),
+            None => (false, Vec::new()),
+        }
+    }
+
+    // `include` as a list, along with the message, level, timestamp and stacktrace fields,
+    // which are always included, even with `exclude: all`.
+    pub fn included(&self) -> Vec<String> {
+        let mut include = match &self.include {
+            Some(Include::IncludeOne(one)) => vec![one.clone()],
+            Some(Include::IncludeMany(many)) => many.clone(),
+            None => Vec::new(),
+        };
+        include.extend(self.message.clone());
+        include.extend(self.level.clone());
+        include.extend(
+            self.timestamp
+                .as_ref()
+                .map(|timestamp| timestamp.name.clone()),
+        );
+        include.extend(self.stacktrace.clone());
+        include
+    }
+
+    // Fields not set here are taken from `defaults`.
+    fn or(self, defaults: Self) -> Self {
+        Self {
+            message: self.message.or(defaults.message),
+            level: self.level.or(defaults.level),
+            timestamp: self.timestamp.or(defaults.timestamp),
+            stacktrace: self.stacktrace.or(defaults.stacktrace),
+            exclude: self.exclude.or(defaults.exclude),
+            include: self.include.or(defaults.include),
+            nested: self.nested,
+        }
+    }
+
+    fn cri() -> Self {
+        Self {
+            message: Some("message".to_string()),
+            timestamp: Some(TimestampField {
+                name: "time".to_string(),
+                format: "rfc3339".to_string(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn docker() -> Self {
+        Self {
+            message: Some("log".to_string()),
+            timestamp: Some(TimestampField {
+                name: "time".to_string(),
+                format: "rfc3339".to_string(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn journal() -> Self {
+        Self {
+            message: Some("MESSAGE".to_string()),
+            level: Some("PRIORITY".to_string()),
+            timestamp: Some(TimestampField {
+                name: "__REALTIME_TIMESTAMP".to_string(),
+                format: "unix_micro".to_string(),
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+// The `fields` of a format type with default fields are merged over those defaults, so
+// setting e.g. `level` keeps the default message and timestamp.
+fn cri_fields<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Fields, D::Error> {
+    Ok(Fields::deserialize(deserializer)?.or(Fields::cri()))
+}
+
+fn docker_fields<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Fields, D::Error> {
+    Ok(Fields::deserialize(deserializer)?.or(Fields::docker()))
+}
+
+fn journal_fields<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Fields, D::Error> {
+    Ok(Fields::deserialize(deserializer)?.or(Fields::journal()))
+}
+
+// How nested JSON objects and arrays are turned into fields.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq)]
+pub enum Nested {
+    // Keep nested values as a single field holding compact JSON.
+    #[default]
+    #[serde(rename = "keep")]
+    Keep,
+    // Keep nested values as a single field holding indented JSON.
+    #[serde(rename = "pretty")]
+    Pretty,
+    // Turn every leaf into its own field with a dotted key, e.g. `http.status`.
+    #[serde(rename = "flatten")]
+    Flatten,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct TimestampField {
     pub name: String,
     pub format: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Layer, Overrides};
+    use crate::config::{FormatType, Nested};
+    use ::config::{File, FileFormat};
+
+    // A json format with highlight settings and examples of its own, to tell whether they're
+    // kept.
+    const HIGHLIGHTED_JSON: &str = "formats:
+  json:
+    type: json
+    fields:
+      message: msg
+    highlight:
+      disable: [number]
+    examples:
+      - line: '{\"msg\": \"hi\"}'
+        fields:
+          message: hi
+";
+
+    fn layer(text: &str) -> Layer {
+        Layer::read(File::from_str(text, FileFormat::Yaml)).unwrap()
+    }
+
+    #[test]
+    fn config_layers() {
+        let config = Config::bundled();
+        assert_eq!(Some("json"), config.default_format.as_deref());
+        let formats = config.formats.len();
+
+        let config = Config::from_layers(
+            vec![Config::bundled_layer(), layer(HIGHLIGHTED_JSON)],
+            &Overrides::default(),
+        )
+        .unwrap();
+        assert_eq!(vec!["number"], config.formats["json"].highlight.disable);
+
+        let config = Config::from_layers(vec![
+            Config::bundled_layer(),
+            layer(HIGHLIGHTED_JSON),
+            layer("formats:\n  json:\n    type: json\n  app:\n    type: docker\ndefault_format: app\n"),
+        ], &Overrides::default())
+        .unwrap();
+        // Formats are replaced as a whole, not merged with the one they override.
+        assert!(config.formats["json"].highlight.disable.is_empty());
+        assert_eq!(formats + 1, config.formats.len());
+        assert_eq!(Some("app"), config.default_format.as_deref());
+        assert!(config.path_matches.contains_key("cri"));
+    }
+
+    #[test]
+    fn format_extends() {
+        let config = Config::from_layers(
+            vec![
+                Config::bundled_layer(),
+                Config::sample_layer(),
+                layer(HIGHLIGHTED_JSON),
+                layer(
+                    "formats:
+  api:
+    extends: nested_json
+    fields:
+      level: severity
+      nested: keep
+  api_v2:
+    extends: api
+    template: '{message}'
+  mine:
+    extends: json
+",
+                ),
+            ],
+            &Overrides::default(),
+        )
+        .unwrap();
+        let format = &config.formats["api_v2"];
+        let FormatType::JsonFormat { fields } = &format.format_type else {
+            panic!("api_v2 isn't a json format");
+        };
+        // Fields are merged key by key through every format extended.
+        assert_eq!(Some("$.log.msg"), fields.message.as_deref());
+        assert_eq!(Some("severity"), fields.level.as_deref());
+        assert_eq!(Nested::Keep, fields.nested);
+        assert!(fields.exclude.is_some());
+        assert_eq!(Some("{message}"), format.template.as_deref());
+        // Examples aren't inherited.
+        assert!(!config.formats["json"].examples.is_empty());
+        assert!(config.formats["mine"].examples.is_empty());
+        assert_eq!(vec!["number"], config.formats["mine"].highlight.disable);
+
+        for (text, error) in [
+            (
+                "formats:\n  a:\n    extends: b\n",
+                "format `a` extends `b`, which doesn't exist",
+            ),
+            (
+                "formats:\n  a:\n    extends: b\n  b:\n    extends: a\n",
+                "extends itself",
+            ),
+        ] {
+            let e = Config::from_layers(vec![layer(text)], &Overrides::default()).unwrap_err();
+            assert!(e.to_string().contains(error), "{}", e);
+        }
+    }
+
+    #[test]
+    fn default_fields() {
+        let config = Config::from_layers(
+            vec![layer(
+                "formats:
+  k8s:
+    type: cri
+    fields:
+      level: level
+  containers:
+    type: docker
+    fields:
+      message: msg
+      exclude: all
+  systemd:
+    type: journal
+    fields:
+      stacktrace: STACK
+",
+            )],
+            &Overrides::default(),
+        )
+        .unwrap();
+        // A format's fields are merged over the defaults of its type.
+        let fields = config.formats["k8s"].format_type.fields();
+        assert_eq!(Some("message"), fields.message.as_deref());
+        assert_eq!(Some("level"), fields.level.as_deref());
+        assert_eq!("time", fields.timestamp.as_ref().unwrap().name);
+        let fields = config.formats["containers"].format_type.fields();
+        assert_eq!(Some("msg"), fields.message.as_deref());
+        assert_eq!("time", fields.timestamp.as_ref().unwrap().name);
+        assert_eq!((true, Vec::new()), fields.excluded());
+        let fields = config.formats["systemd"].format_type.fields();
+        assert_eq!(Some("MESSAGE"), fields.message.as_deref());
+        assert_eq!(Some("PRIORITY"), fields.level.as_deref());
+        assert_eq!(Some("STACK"), fields.stacktrace.as_deref());
+    }
+
+    #[test]
+    fn config_overrides() {
+        let vars = [
+            ("FALOG_CONFIG", "ignored.yml"),
+            ("FALOG_FORMATS__JSON__FIELDS__MESSAGE", "text"),
+            ("FALOG_FORMATS__JSON__FIELDS__LEVEL", "severity"),
+            ("FALOG_STACKTRACE__COLLAPSE", "false"),
+            ("FALOG_HOME", "/opt/falog"),
+            ("FALOG_FORMATS__JSON__FEILDS__MESSAGE", "text"),
+            ("HOME", "/root"),
+        ]
+        .map(|(name, value)| (name.into(), value.into()));
+        let sets = [
+            "formats.json.fields.message=msg",
+            "default_format=logfmt",
+            "theme.styles.level.error=bold red",
+            "formats.batch_csv.columns=[time, job]",
+            "formats.nginx.fields.timestamp.format=[%d/%b/%Y:%H:%M:%S %z]",
+        ]
+        .map(String::from);
+        let overrides = Overrides::new(vars, &sets).unwrap();
+        // Variables that aren't config keys are left out.
+        assert_eq!(
+            vec!["FALOG_FORMATS__JSON__FEILDS__MESSAGE", "FALOG_HOME"],
+            overrides.skipped
+        );
+        let config = Config::from_layers(
+            vec![
+                Config::bundled_layer(),
+                Config::sample_layer(),
+                layer(HIGHLIGHTED_JSON),
+                layer("theme:\n  styles:\n    level.error: blue\n    level.warn: yellow\n"),
+            ],
+            &overrides,
+        )
+        .unwrap();
+
+        let fields = config.formats["json"].format_type.fields();
+        // `--set` is applied after the environment.
+        assert_eq!(Some("msg"), fields.message.as_deref());
+        assert_eq!(Some("severity"), fields.level.as_deref());
+        // The rest of the format stays.
+        assert_eq!(vec!["number"], config.formats["json"].highlight.disable);
+        assert!(!config.stacktrace.collapse);
+        assert_eq!(Some("logfmt"), config.default_format.as_deref());
+        assert_eq!("bold red", config.theme.styles["level.error"]);
+        assert_eq!("yellow", config.theme.styles["level.warn"]);
+        let FormatType::CsvFormat { columns, .. } = &config.formats["batch_csv"].format_type else {
+            panic!("batch_csv isn't a csv format");
+        };
+        assert_eq!(Some(vec!["time".to_string(), "job".to_string()]), *columns);
+        // Only keys holding lists take `[a, b]` as a list.
+        let fields = config.formats["nginx"].format_type.fields();
+        assert_eq!(
+            "[%d/%b/%Y:%H:%M:%S %z]",
+            fields.timestamp.as_ref().unwrap().format
+        );
+
+        for (set, error) in [
+            ("nope", "--set `nope` isn't key=value"),
+            ("colour=red", "--set colour=red: unknown key `colour`"),
+            (
+                "formats.json.feilds.message=text",
+                "--set formats.json.feilds.message=text: unknown key `formats.json.feilds`, expected one of: type, extends,",
+            ),
+            (
+                "include=[a.yml]",
+                "--set include=[a.yml]: `include` can only be set in config files",
+            ),
+            (
+                "stacktrace.collapse=maybe",
+                "invalid type: string \"maybe\"",
+            ),
+        ] {
+            let e = Overrides::new([], &[set.to_string()])
+                .and_then(|overrides| {
+                    Config::from_layers(vec![Config::bundled_layer()], &overrides)
+                })
+                .unwrap_err();
+            assert!(e.to_string().starts_with(error), "{}", e);
+        }
+    }
+}
@@ -0,0 +1,590 @@
+use crate::{
+    config::{
+        self, Config, Format, FormatType, Highlight, Overrides, PayloadType, Stacktrace, Theme,
+        BUNDLED_CONFIG, TOP_KEYS,
+    },
+    highlight::{Highlighter, Rule},
+    reader_builder::ReaderBuilder,
+    reader_json::{normalize_fields, JsonParser},
+    template::Template,
+    theme, timestamp,
+};
+use ::config::{File, FileFormat, Value};
+use anyhow::anyhow;
+use regex::Regex;
+use std::{collections::HashMap, fmt, fs, path::Path};
+use yaml_rust::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::Marker,
+};
+
+// What problems of the keys set with FALOG_ variables and `--set` are reported as in.
+pub const OVERRIDES: &str = "<overrides>";
+
+const FORMAT_KEYS: &[&str] = &["type", "extends", "template", "highlight", "examples"];
+const FIELDS_KEYS: &[&str] = &[
+    "message",
+    "level",
+    "timestamp",
+    "stacktrace",
+    "exclude",
+    "include",
+    "nested",
+];
+
+// Something wrong with a config file, found by `falog config check`.
+#[derive(Debug)]
+pub struct Problem {
+    pub file: String,
+    // Line and column, both starting from 1.
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => {
+                write!(f, "{}:{}:{}: {}", self.file, line, column, self.message)
+            }
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+// The bundled config and the config files that are read, including the ones they include, by name, along with their contents.
+pub fn files(explicit: Option<&Path>) -> Vec<(String, anyhow::Result<String>)> {
+    let mut files = vec![("<bundled>".to_string(), Ok(BUNDLED_CONFIG.to_string()))];
+    for path in Config::files(explicit) {
+        let paths = match config::with_includes(&path) {
+            Ok(paths) => paths,
+            Err(e) => {
+                files.push((path.display().to_string(), Err(e)));
+                continue;
+            }
+        };
+        for path in paths {
+            let text = fs::read_to_string(&path).map_err(anyhow::Error::from);
+            files.push((path.display().to_string(), text));
+        }
+    }
+    files
+}
+
+// Checks the files as if they were layered by `Config::load`: every format is compiled,
+// timestamp formats, templates and highlight rules are validated, and `path_matches` and
+// `default_format` must name a format defined in one of the files. Unknown keys are reported
+// too, as they're otherwise silently ignored.
+pub fn check(files: &[(String, anyhow::Result<String>)]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut locations = Vec::new();
+    // The last definition of each, along with the index of the file defining it.
+    let mut formats_defined = HashMap::new();
+    let mut path_matches = HashMap::new();
+    let mut default_format = None;
+
+    for (i, (file, text)) in files.iter().enumerate() {
+        let mut problem = |path: &[&str], locations: &Locations, message: String| {
+            problems.push(Problem {
+                file: file.clone(),
+                location: locations.find(path),
+                message,
+            });
+        };
+
+        let text = match text {
+            Ok(text) => text,
+            Err(e) => {
+                problem(&[], &Locations::default(), e.to_string());
+                locations.push(Locations::default());
+                continue;
+            }
+        };
+        let root = match Locations::parse(text).and_then(|found| {
+            let root = ::config::Config::builder()
+                .add_source(File::from_str(text, FileFormat::Yaml))
+                .build()
+                .and_then(|config| config.try_deserialize::<HashMap<String, Value>>())
+                .map_err(|e| e.to_string())?;
+            Ok((found, root))
+        }) {
+            Ok((found, root)) => {
+                locations.push(found);
+                root
+            }
+            Err(e) => {
+                problem(&[], &Locations::default(), e);
+                locations.push(Locations::default());
+                continue;
+            }
+        };
+        let found = &locations[i];
+
+        for (path, _) in &found.keys {
+            let (key, parent) = path.split_last().unwrap();
+            if let Some(allowed) = found.allowed_keys(parent) {
+                if !allowed.contains(&key.as_str()) {
+                    let path: Vec<_> = path.iter().map(String::as_str).collect();
+                    problem(
+                        &path,
+                        found,
+                        format!(
+                            "unknown key `{}`, expected one of: {}",
+                            key,
+                            allowed.join(", ")
+                        ),
+                    );
+                }
+            }
+        }
+
+        let mut keys: Vec<_> = root.into_iter().collect();
+        keys.sort_by_key(|(key, _)| found.find(&[key]));
+        for (key, value) in keys {
+            match key.as_str() {
+                "formats" => {
+                    let formats = match value.into_table() {
+                        Ok(formats) => formats,
+                        Err(e) => {
+                            problem(&["formats"], found, e.to_string());
+                            continue;
+                        }
+                    };
+                    for (name, value) in formats {
+                        formats_defined.insert(name, (i, value));
+                    }
+                }
+                "default_format" => match value.into_string() {
+                    Ok(name) => default_format = Some((i, name)),
+                    Err(e) => problem(&["default_format"], found, e.to_string()),
+                },
+                "path_matches" => match value.try_deserialize::<HashMap<String, String>>() {
+                    Ok(matches) => {
+                        for (name, pattern) in matches {
+                            if let Err(e) = Regex::new(&pattern) {
+                                let message =
+                                    format!("path match `{}`: invalid regex: {}", name, e);
+                                problem(&["path_matches", &name], found, message);
+                            }
+                            path_matches.insert(name, i);
+                        }
+                    }
+                    Err(e) => problem(&["path_matches"], found, e.to_string()),
+                },
+                "stacktrace" => {
+                    if let Err(e) = value.try_deserialize::<Stacktrace>() {
+                        problem(&["stacktrace"], found, format!("stacktrace: {}", e));
+                    }
+                }
+                "theme" => {
+                    let checked = value
+                        .try_deserialize::<Theme>()
+                        .map_err(|e| anyhow!("theme: {}", e))
+                        .and_then(|config| theme::Theme::from_config(&config));
+                    if let Err(e) = checked {
+                        problem(&["theme"], found, e.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Formats may extend or be referred to by a format of another file, so they're checked
+    // once every file is read.
+    let mut problem = |i: usize, path: &[&str], message: String| {
+        problems.push(Problem {
+            file: files[i].0.clone(),
+            location: locations[i].find(path),
+            message,
+        });
+    };
+    let formats: HashMap<_, _> = formats_defined
+        .iter()
+        .map(|(name, (_, value))| (name.clone(), value.clone()))
+        .collect();
+    for (name, (i, _)) in &formats_defined {
+        let format = config::resolve_format(name, &formats).and_then(|value| {
+            value
+                .try_deserialize::<Format>()
+                .map_err(|e| anyhow!("format `{}`: {}", name, e))
+        });
+        match format {
+            Ok(format) => {
+                for (key, message) in check_format(&format) {
+                    let mut path = vec!["formats", name];
+                    path.extend(key.split('.'));
+                    problem(*i, &path, format!("format `{}`: {}", name, message));
+                }
+            }
+            Err(e) => problem(*i, &["formats", name], e.to_string()),
+        }
+    }
+
+    let mut path_matches: Vec<_> = path_matches.into_iter().collect();
+    path_matches.sort();
+    for (name, i) in path_matches {
+        if !formats.contains_key(&name) {
+            let message = format!("path match `{}`: there's no format with that name", name);
+            problem(i, &["path_matches", &name], message);
+        }
+    }
+    if let Some((i, name)) = default_format {
+        if !formats.contains_key(&name) {
+            let message = format!("default_format: there's no format with name `{}`", name);
+            problem(i, &["default_format"], message);
+        }
+    }
+
+    // In the order of the files, then of the lines.
+    problems.sort_by_key(|problem| {
+        let file = files.iter().position(|(file, _)| *file == problem.file);
+        (file, problem.location)
+    });
+    problems
+}
+
+// Problems of the keys set with FALOG_ variables and `--set`: unknown keys, and if `load` is
+// set, whatever keeps the config from loading with them.
+pub fn check_overrides(explicit: Option<&Path>, overrides: &Overrides) -> Vec<Problem> {
+    let loaded = Config::load(explicit, overrides).and_then(|config| {
+        theme::Theme::from_config(&config.theme)?;
+        ReaderBuilder::new(config)
+    });
+    match loaded {
+        Ok(_) => Vec::new(),
+        Err(e) => vec![Problem {
+            file: OVERRIDES.to_string(),
+            location: None,
+            message: e.to_string(),
+        }],
+    }
+}
+
+// Why the key at `path` can't be in a config file, if it can't. The type of a format isn't
+// known, so a key of any format type is fine in a format.
+pub fn unknown_key(path: &[&str]) -> Option<String> {
+    for len in 0..path.len() {
+        let (key, parent) = path[..=len].split_last().unwrap();
+        let Some(allowed) = allowed_keys(parent, |_| None) else {
+            continue;
+        };
+        if !allowed.contains(key) {
+            return Some(format!(
+                "unknown key `{}`, expected one of: {}",
+                path[..=len].join("."),
+                allowed.join(", ")
+            ));
+        }
+    }
+    None
+}
+
+// Problems of a format that parsed, by the dotted path of the key they're about.
+fn check_format(format: &Format) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+    let fields = format.format_type.fields();
+
+    let json = match &format.format_type {
+        FormatType::JsonFormat { .. } => true,
+        FormatType::RegexFormat {
+            format, payload, ..
+        } => match Regex::new(format) {
+            Ok(re) => match payload {
+                Some(payload) => {
+                    if !re
+                        .capture_names()
+                        .flatten()
+                        .any(|name| name == payload.capture)
+                    {
+                        problems.push((
+                            "payload.capture".to_string(),
+                            format!("`{}` is not a named capture of the format", payload.capture),
+                        ));
+                    }
+                    payload.payload_type == PayloadType::Json
+                }
+                None => false,
+            },
+            Err(e) => {
+                problems.push(("format".to_string(), format!("invalid regex: {}", e)));
+                false
+            }
+        },
+        FormatType::CsvFormat {
+            delimiter, quote, ..
+        } => {
+            for (key, c) in [("delimiter", delimiter), ("quote", quote)] {
+                if !c.is_ascii() {
+                    problems.push((key.to_string(), format!("`{}` isn't an ASCII character", c)));
+                }
+            }
+            false
+        }
+        _ => false,
+    };
+
+    if json {
+        if let Err(e) = normalize_fields(fields).and_then(|_| JsonParser::new(fields.clone())) {
+            problems.push(("fields".to_string(), e.to_string()));
+        }
+    }
+    if let Some(timestamp) = &fields.timestamp {
+        if let Err(e) = timestamp::check_format(&timestamp.format) {
+            problems.push(("fields.timestamp.format".to_string(), e.to_string()));
+        }
+    }
+    if let Some(template) = &format.template {
+        let known = ReaderBuilder::format_known_fields(&format.format_type);
+        let checked = Template::parse(template)
+            .and_then(|template| template.validate(fields, known.as_deref()));
+        if let Err(e) = checked {
+            problems.push(("template".to_string(), e.to_string()));
+        }
+    }
+    for (i, rule) in format.highlight.rules.iter().enumerate() {
+        if let Err(e) = Rule::custom(&rule.pattern, rule.style.as_deref()) {
+            problems.push((format!("highlight.rules.{}", i), e.to_string()));
+        }
+    }
+    let disable = Highlight {
+        disable: format.highlight.disable.clone(),
+        rules: Vec::new(),
+    };
+    if let Err(e) = Highlighter::new(&disable, &[]) {
+        problems.push(("highlight.disable".to_string(), e.to_string()));
+    }
+
+    problems
+}
+
+// The keys a mapping at `path` can have, None if any key is fine. `value` looks up the value
+// of a key, by path, to know the type of a format.
+fn allowed_keys<'a>(
+    path: &[&str],
+    value: impl Fn(&[&str]) -> Option<&'a str>,
+) -> Option<Vec<&'static str>> {
+    let keys = match path {
+        [] => TOP_KEYS.to_vec(),
+        ["formats", name] => {
+            let value = |key: &str| value(&["formats", name, key]);
+            let type_keys: &[&str] = match value("type") {
+                Some("regex") => &["format", "fields", "payload"],
+                Some("csv") => &["delimiter", "quote", "header", "columns", "fields"],
+                Some("json" | "cri" | "journal" | "docker") => &["fields"],
+                // The type is the one of the extended format, or of the format in another
+                // file that `--set` and FALOG_ variables override.
+                None => &[
+                    "format",
+                    "fields",
+                    "payload",
+                    "delimiter",
+                    "quote",
+                    "header",
+                    "columns",
+                ],
+                _ => return None,
+            };
+            [FORMAT_KEYS, type_keys].concat()
+        }
+        ["formats", _, "fields"] => FIELDS_KEYS.to_vec(),
+        ["formats", _, "fields", "timestamp"] => vec!["name", "format"],
+        ["formats", _, "payload"] => vec!["capture", "type"],
+        ["formats", _, "highlight"] => vec!["disable", "rules"],
+        ["formats", _, "highlight", "rules", _] => vec!["pattern", "style"],
+        ["formats", _, "examples", _] => vec!["line", "fields"],
+        ["stacktrace"] => vec!["app_packages", "collapse"],
+        ["theme"] => vec!["name", "styles"],
+        _ => return None,
+    };
+    Some(keys)
+}
+
+// Where the keys of a YAML document are. Items of sequences are keyed by their index.
+#[derive(Default)]
+struct Locations {
+    keys: Vec<(Vec<String>, Marker)>,
+    // Scalar values, by path.
+    values: HashMap<Vec<String>, String>,
+    stack: Vec<Frame>,
+}
+
+enum Frame {
+    Mapping {
+        path: Vec<String>,
+        key: Option<String>,
+    },
+    Sequence {
+        path: Vec<String>,
+        index: usize,
+    },
+}
+
+impl Locations {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut locations = Self::default();
+        Parser::new(text.chars())
+            .load(&mut locations, false)
+            .map_err(|e| e.to_string())?;
+        Ok(locations)
+    }
+
+    // Where the key at `path` is, or its closest parent if it isn't in the document.
+    fn find(&self, path: &[&str]) -> Option<(usize, usize)> {
+        (1..=path.len()).rev().find_map(|len| {
+            self.keys
+                .iter()
+                .find(|(key, _)| {
+                    key.iter()
+                        .map(String::as_str)
+                        .eq(path[..len].iter().copied())
+                })
+                .map(|(_, mark)| (mark.line(), mark.col() + 1))
+        })
+    }
+
+    // The keys a mapping at `path` can have, None if any key is fine.
+    fn allowed_keys(&self, path: &[String]) -> Option<Vec<&'static str>> {
+        let path: Vec<_> = path.iter().map(String::as_str).collect();
+        allowed_keys(&path, |key| {
+            let key = key.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+            self.values.get(&key).map(String::as_str)
+        })
+    }
+
+    // The path of the value that starts at `mark`. Items of sequences are located as keys.
+    fn value_path(&mut self, mark: Marker) -> Vec<String> {
+        match self.stack.last() {
+            Some(Frame::Mapping { path, key }) => {
+                let mut path = path.clone();
+                path.extend(key.clone());
+                path
+            }
+            Some(Frame::Sequence { path, index }) => {
+                let mut path = path.clone();
+                path.push(index.to_string());
+                self.keys.push((path.clone(), mark));
+                path
+            }
+            None => Vec::new(),
+        }
+    }
+
+    // Moves on from the value that just ended.
+    fn advance(&mut self) {
+        match self.stack.last_mut() {
+            Some(Frame::Mapping { key, .. }) => *key = None,
+            Some(Frame::Sequence { index, .. }) => *index += 1,
+            None => {}
+        }
+    }
+}
+
+impl MarkedEventReceiver for Locations {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, ..) => {
+                if let Some(Frame::Mapping { path, key: None }) = self.stack.last() {
+                    let mut path = path.clone();
+                    path.push(value.clone());
+                    self.keys.push((path, mark));
+                    if let Some(Frame::Mapping { key, .. }) = self.stack.last_mut() {
+                        *key = Some(value);
+                    }
+                } else {
+                    let path = self.value_path(mark);
+                    self.values.insert(path, value);
+                    self.advance();
+                }
+            }
+            Event::Alias(_) => {
+                self.value_path(mark);
+                self.advance();
+            }
+            Event::MappingStart(_) => {
+                let path = self.value_path(mark);
+                self.stack.push(Frame::Mapping { path, key: None });
+            }
+            Event::SequenceStart(_) => {
+                let path = self.value_path(mark);
+                self.stack.push(Frame::Sequence { path, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+                self.advance();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, check_overrides};
+    use crate::config::{Overrides, BUNDLED_CONFIG, SAMPLE_CONFIG};
+    use std::path::Path;
+
+    #[test]
+    fn config_problems() {
+        let bundled = || ("<bundled>".to_string(), Ok(BUNDLED_CONFIG.to_string()));
+        assert!(check(&[bundled()]).is_empty());
+        let sample = (
+            "falog_sample.yml".to_string(),
+            Ok(SAMPLE_CONFIG.to_string()),
+        );
+        assert!(check(&[bundled(), sample]).is_empty());
+
+        let text = "\
+formats:
+  app:
+    type: regex
+    format: \"(?P<level>\\\\w+\"
+    feilds:
+      message: msg
+  api:
+    type: json
+    fields:
+      timestamp:
+        name: ts
+        format: rfc339
+    template: \"{message\"
+default_format: nope
+path_matches:
+  app: \"\\\\.log$\"
+  web: \"^/var/log/web/\"
+";
+        let file = ("falog.yml".to_string(), Ok(text.to_string()));
+        let problems: Vec<_> = check(&[bundled(), file])
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "falog.yml:4:5: format `app`: invalid regex: regex parse error:\n    (?P<level>\\w+\n    ^\nerror: unclosed group",
+                "falog.yml:5:5: unknown key `feilds`, expected one of: type, extends, template, highlight, examples, format, fields, payload",
+                "falog.yml:12:9: format `api`: unknown timestamp format `rfc339`. use unix, unix_milli, unix_micro, unix_nano, rfc3339, rfc2822 or a strftime pattern",
+                "falog.yml:13:5: format `api`: unclosed `{` in template `{message`",
+                "falog.yml:14:1: default_format: there's no format with name `nope`",
+                "falog.yml:17:3: path match `web`: there's no format with that name",
+            ],
+            problems
+        );
+    }
+
+    #[test]
+    fn override_problems() {
+        let sample = Some(Path::new("falog_sample.yml"));
+        let check = |set: &str| {
+            let overrides = Overrides::new([], &[set.to_string()]).unwrap();
+            check_overrides(sample, &overrides)
+                .iter()
+                .map(|problem| problem.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert!(check("formats.batch_csv.columns=[time, job]").is_empty());
+        assert_eq!(
+            vec!["<overrides>: format `nested_json`: template refers to the unknown field `mesage`. did you mean `message`?"],
+            check("formats.nested_json.template={mesage}")
+        );
+    }
+}
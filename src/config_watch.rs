@@ -0,0 +1,173 @@
+use crate::{
+    config::{self, Config, Overrides},
+    highlight::{Highlighters, Rule},
+    new_async_watcher,
+    output::{ConfigOptions, SharedOutput},
+    reader_builder::ReaderBuilder,
+    theme::Theme,
+};
+use anyhow::anyhow;
+use futures::StreamExt;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::watch;
+
+// The reader builder of the latest config that loaded.
+pub type Reloads = watch::Receiver<Arc<ReaderBuilder>>;
+
+// Reloads the config whenever one of its files changes, starting with `builder`. Readers get
+// the new formats through the returned receiver, the output gets the rest. A config that
+// doesn't load is reported to the output and the old one stays in use.
+pub fn watch(
+    explicit: Option<PathBuf>,
+    overrides: Overrides,
+    custom_rules: Vec<Rule>,
+    builder: Arc<ReaderBuilder>,
+    output: SharedOutput,
+) -> anyhow::Result<Reloads> {
+    let (mut watcher, mut events) = new_async_watcher().map_err(|e| anyhow!(e))?;
+    let mut files = HashSet::new();
+    let mut dirs = HashSet::new();
+    watch_files(&mut watcher, explicit.as_deref(), &mut files, &mut dirs)?;
+    let (tx, rx) = watch::channel(builder);
+
+    tokio::spawn(async move {
+        // Events stop once the watcher is dropped.
+        let mut watcher = watcher;
+        while let Some(event) = events.next().await {
+            let changed = match event {
+                Ok(event) => {
+                    matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                    ) && event.paths.iter().any(|path| files.contains(path))
+                }
+                Err(e) => {
+                    output
+                        .lock()
+                        .unwrap()
+                        .reload_error(&anyhow!("watch error: {}", e));
+                    false
+                }
+            };
+            if !changed {
+                continue;
+            }
+            // Editors save in more than one step, reload once they're done.
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            while let Ok(Some(_)) = events.try_next() {}
+
+            match load(explicit.as_deref(), &overrides, &custom_rules) {
+                Ok((builder, options)) => {
+                    output.lock().unwrap().reload(options);
+                    // Fails only if every reader is done.
+                    let _ = tx.send(Arc::new(builder));
+                }
+                Err(e) => output.lock().unwrap().reload_error(&e),
+            }
+            // The files may include others now.
+            if let Err(e) = watch_files(&mut watcher, explicit.as_deref(), &mut files, &mut dirs) {
+                output.lock().unwrap().reload_error(&e);
+            }
+        }
+    });
+    Ok(rx)
+}
+
+fn load(
+    explicit: Option<&Path>,
+    overrides: &Overrides,
+    custom_rules: &[Rule],
+) -> anyhow::Result<(ReaderBuilder, ConfigOptions)> {
+    let config = Config::load(explicit, overrides)?;
+    let highlighters = Highlighters::new(&config, custom_rules)?;
+    let theme = Theme::from_config(&config.theme)?;
+    let stacktrace = config.stacktrace.clone();
+    let builder = ReaderBuilder::new(config)?;
+    let options = ConfigOptions {
+        theme,
+        format_templates: builder.templates(),
+        stacktrace,
+        highlighters,
+    };
+    Ok((builder, options))
+}
+
+// Watches the directories of the config files rather than the files themselves, since editors
+// often save a file by replacing it. `files` are canonical paths, like the paths of events.
+//
+// Config files that don't exist yet are watched for too, so that e.g. a `falog.yml` created
+// later is loaded. If its directory doesn't exist either, the nearest one that does is watched
+// for the missing directory, which is watched in turn once it's created.
+fn watch_files(
+    watcher: &mut impl Watcher,
+    explicit: Option<&Path>,
+    files: &mut HashSet<PathBuf>,
+    dirs: &mut HashSet<PathBuf>,
+) -> anyhow::Result<()> {
+    files.clear();
+    for path in Config::search_paths(explicit) {
+        // Includes that can't be read are reported when the config is loaded.
+        let paths = config::with_includes(&path).unwrap_or_else(|_| vec![path]);
+        for path in paths {
+            let Some((dir, path)) = watched_path(&path) else {
+                continue;
+            };
+            if dirs.insert(dir.clone()) {
+                watcher
+                    .watch(&dir, RecursiveMode::NonRecursive)
+                    .map_err(|e| anyhow!("can't watch `{}`: {}", dir.display(), e))?;
+            }
+            files.insert(path);
+        }
+    }
+    Ok(())
+}
+
+// The canonical directory to watch for `path` and the path in it that stands for `path`: `path`
+// itself if it exists, or else its first missing component.
+fn watched_path(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let path = std::env::current_dir().ok()?.join(path);
+    let mut missing = None;
+    let mut existing = path.as_path();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return match missing {
+                None => Some((canonical.parent()?.to_path_buf(), canonical)),
+                Some(name) => Some((canonical.clone(), canonical.join(name))),
+            };
+        }
+        missing = Some(existing.file_name()?);
+        existing = existing.parent()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::watched_path;
+    use std::fs;
+
+    #[test]
+    fn watched_paths() {
+        let dir = std::env::temp_dir().join(format!("falog-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let file = dir.join("falog.yml");
+
+        // A missing file is watched for in its directory, a missing directory in its parent.
+        assert_eq!(Some((dir.clone(), file.clone())), watched_path(&file));
+        assert_eq!(
+            Some((dir.clone(), dir.join("falog"))),
+            watched_path(&dir.join("falog").join("falog.yml"))
+        );
+        fs::write(&file, "").unwrap();
+        assert_eq!(Some((dir.clone(), file.clone())), watched_path(&file));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
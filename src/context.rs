@@ -0,0 +1,119 @@
+use crate::{filter::Filter, output::Record};
+use regex::Regex;
+use std::collections::VecDeque;
+
+// Which records are shown: the ones matching both the filter and the grep regex, with
+// `before` and `after` records of context around them like `grep -B` and `grep -A`.
+pub struct Selection {
+    pub filter: Option<Filter>,
+    // Matched against the record as it was read.
+    pub grep: Option<Regex>,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl Selection {
+    pub fn matches(&self, record: &Record) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(record))
+            && self
+                .grep
+                .as_ref()
+                .is_none_or(|grep| grep.is_match(record.raw))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Emit<T> {
+    // Between groups of records that aren't next to each other, like grep's `--`.
+    Separator,
+    Record(T),
+}
+
+// Keeps the records of a single source that may turn out to be context of a later match.
+pub struct Window<T> {
+    before: usize,
+    after: usize,
+    // Records that didn't match, along with their index.
+    buffer: VecDeque<(u64, T)>,
+    after_left: usize,
+    next_index: u64,
+    last_emitted: Option<u64>,
+}
+
+impl<T> Window<T> {
+    pub fn new(before: usize, after: usize) -> Self {
+        Self {
+            before,
+            after,
+            buffer: VecDeque::with_capacity(before),
+            after_left: 0,
+            next_index: 0,
+            last_emitted: None,
+        }
+    }
+
+    // Takes the next record of the source and returns what should be written, in order.
+    pub fn push(&mut self, record: T, matched: bool) -> Vec<Emit<T>> {
+        let index = self.next_index;
+        self.next_index += 1;
+
+        let mut emitted = Vec::new();
+        if matched {
+            for (index, record) in std::mem::take(&mut self.buffer) {
+                self.emit(index, record, &mut emitted);
+            }
+            self.emit(index, record, &mut emitted);
+            self.after_left = self.after;
+        } else if self.after_left > 0 {
+            self.after_left -= 1;
+            self.emit(index, record, &mut emitted);
+        } else if self.before > 0 {
+            if self.buffer.len() == self.before {
+                self.buffer.pop_front();
+            }
+            self.buffer.push_back((index, record));
+        }
+        emitted
+    }
+
+    fn emit(&mut self, index: u64, record: T, emitted: &mut Vec<Emit<T>>) {
+        if let Some(last) = self.last_emitted {
+            let context = self.before > 0 || self.after > 0;
+            if context && index != last + 1 {
+                emitted.push(Emit::Separator);
+            }
+        }
+        self.last_emitted = Some(index);
+        emitted.push(Emit::Record(record));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Emit, Window};
+
+    #[test]
+    fn context_window() {
+        let mut window = Window::new(1, 1);
+        let mut emitted = Vec::new();
+        for (i, matched) in [false, false, true, false, false, false, true, false, true]
+            .into_iter()
+            .enumerate()
+        {
+            emitted.extend(window.push(i, matched));
+        }
+        let expected = [
+            Emit::Record(1),
+            Emit::Record(2),
+            Emit::Record(3),
+            Emit::Separator,
+            Emit::Record(5),
+            Emit::Record(6),
+            Emit::Record(7),
+            Emit::Record(8),
+        ];
+        assert_eq!(Vec::from(expected), emitted);
+    }
+}
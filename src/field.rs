@@ -1,5 +1,63 @@
-#[derive(Debug)]
+use crate::config::Fields;
+use indexmap::IndexMap;
+
+// Fields of a record, in the order they appear in the input.
+pub type FieldMap = IndexMap<String, Field>;
+
+#[derive(Debug, Clone)]
 pub struct Field {
     pub name: String,
     pub value: String,
+    pub kind: Kind,
+}
+
+// The type of a value as found in the input. Only JSON input carries types,
+// everything else is text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    String,
+    Number,
+    Bool,
+    Null,
+    // An object or an array, `value` holds its JSON.
+    Json,
+}
+
+impl Field {
+    pub fn new(name: String, value: String) -> Self {
+        Self {
+            name,
+            value,
+            kind: Kind::String,
+        }
+    }
+
+    pub fn typed(name: String, value: String, kind: Kind) -> Self {
+        Self { name, value, kind }
+    }
+}
+
+// `exclude` and `include` of a format's fields, for readers whose fields are flat. JSON has
+// its own, as it selects nested values too.
+pub struct FieldFilter {
+    exclude_all: bool,
+    exclude: Vec<String>,
+    include: Vec<String>,
+}
+
+impl FieldFilter {
+    pub fn new(fields: &Fields) -> Self {
+        let (exclude_all, exclude) = fields.excluded();
+        Self {
+            exclude_all,
+            exclude,
+            include: fields.included(),
+        }
+    }
+
+    pub fn apply(&self, map: &mut FieldMap) {
+        map.retain(|name, _| {
+            self.include.contains(name) || !(self.exclude_all || self.exclude.contains(name))
+        });
+    }
 }
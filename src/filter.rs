@@ -0,0 +1,379 @@
+use crate::{level::Level, output::Record, timestamp};
+use anyhow::{anyhow, bail};
+use regex::Regex;
+use std::{cmp::Ordering, fmt};
+
+// Filter expressions, e.g. `level >= warn && (status == 500 || path ~ "^/api")`.
+//
+// - `field == value` and `field != value` compare as numbers if both sides are numbers,
+//   as levels for `level`, as times for `timestamp` (or `ts`) and as text otherwise.
+// - `<`, `<=`, `>` and `>=` compare the same way.
+// - `field ~ regex` and `field !~ regex` match a regex.
+// - A lone `field` is true if the record has that field and it isn't empty.
+// - `&&` (or `and`), `||` (or `or`), `!` (or `not`) and parentheses combine them.
+// - Values are bare words or quoted with `"` or `'`.
+// `message`, `level`, `timestamp` and `stacktrace` refer to the fields set in the format's
+// `fields`, or to a field of that name if the format doesn't set them.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Exists(String),
+    Compare {
+        field: String,
+        op: Op,
+        value: String,
+    },
+    Match {
+        field: String,
+        negated: bool,
+        regex: Regex,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        }
+    }
+
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering == Ordering::Equal,
+            Self::Ne => ordering != Ordering::Equal,
+            Self::Lt => ordering == Ordering::Less,
+            Self::Le => ordering != Ordering::Greater,
+            Self::Gt => ordering == Ordering::Greater,
+            Self::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    // A field name or a bare value.
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    Match,
+    NotMatch,
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let two: String = chars.clone().take(2).collect();
+        let token = match two.as_str() {
+            "==" => Some(Token::Op(Op::Eq)),
+            "!=" => Some(Token::Op(Op::Ne)),
+            "<=" => Some(Token::Op(Op::Le)),
+            ">=" => Some(Token::Op(Op::Ge)),
+            "!~" => Some(Token::NotMatch),
+            "&&" => Some(Token::And),
+            "||" => Some(Token::Or),
+            _ => None,
+        };
+        if let Some(token) = token {
+            chars.next();
+            chars.next();
+            tokens.push(token);
+            continue;
+        }
+
+        let token = match c {
+            '<' => Token::Op(Op::Lt),
+            '>' => Token::Op(Op::Gt),
+            '~' => Token::Match,
+            '!' => Token::Not,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '"' | '\'' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => value.push(escaped),
+                            None => bail!("unterminated string in `{}`", input),
+                        },
+                        Some(end) if end == c => break,
+                        Some(c) => value.push(c),
+                        None => bail!("unterminated string in `{}`", input),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+                continue;
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "=!<>~()&|\"'".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                // A lone `=`, `&` or `|`.
+                if word.is_empty() {
+                    bail!("unexpected `{}` in `{}`", c, input);
+                }
+                tokens.push(match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                });
+                continue;
+            }
+        };
+        chars.next();
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn or(&mut self) -> anyhow::Result<Filter> {
+        let mut filter = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Filter> {
+        let mut filter = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Filter> {
+        match self.next() {
+            Some(Token::Not) => Ok(Filter::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let filter = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(filter),
+                    _ => bail!("missing `)`"),
+                }
+            }
+            Some(Token::Word(field)) | Some(Token::Quoted(field)) => self.comparison(field),
+            Some(token) => bail!("expected a field, found {:?}", token),
+            None => bail!("expected a field, found the end of the filter"),
+        }
+    }
+
+    fn comparison(&mut self, field: String) -> anyhow::Result<Filter> {
+        let op = match self.peek() {
+            Some(Token::Op(op)) => Some(*op),
+            Some(Token::Match) | Some(Token::NotMatch) => None,
+            _ => return Ok(Filter::Exists(field)),
+        };
+        let negated = self.next() == Some(Token::NotMatch);
+        let value = match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+            _ => bail!("expected a value after `{}`", field),
+        };
+
+        Ok(match op {
+            Some(op) => Filter::Compare { field, op, value },
+            None => Filter::Match {
+                field,
+                negated,
+                regex: Regex::new(&value)
+                    .map_err(|e| anyhow!("invalid regex `{}`: {}", value, e))?,
+            },
+        })
+    }
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> anyhow::Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+        };
+        let filter = parser
+            .or()
+            .map_err(|e| anyhow!("invalid filter `{}`: {}", input, e))?;
+        if parser.position < parser.tokens.len() {
+            bail!(
+                "invalid filter `{}`: unexpected {:?}",
+                input,
+                parser.tokens[parser.position]
+            );
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        match self {
+            Self::And(left, right) => left.matches(record) && right.matches(record),
+            Self::Or(left, right) => left.matches(record) || right.matches(record),
+            Self::Not(filter) => !filter.matches(record),
+            Self::Exists(field) => record.value(field).is_some_and(|value| !value.is_empty()),
+            Self::Match {
+                field,
+                negated,
+                regex,
+            } => match record.value(field) {
+                Some(value) => regex.is_match(value) != *negated,
+                None => *negated,
+            },
+            Self::Compare { field, op, value } => match compare(record, field, value) {
+                Some(ordering) => op.holds(ordering),
+                // A missing field isn't equal to anything.
+                None => *op == Op::Ne,
+            },
+        }
+    }
+}
+
+// How the field compares to `expected`, None if the record doesn't have it.
+fn compare(record: &Record, field: &str, expected: &str) -> Option<Ordering> {
+    let actual = record.value(field)?;
+
+    match field {
+        "level" => {
+            if let (Some(actual), Some(expected)) = (Level::parse(actual), Level::parse(expected)) {
+                return Some(actual.cmp(&expected));
+            }
+        }
+        "timestamp" | "ts" => {
+            let expected = timestamp::parse(expected, "rfc3339")
+                .or_else(|| timestamp::parse(expected, "%Y-%m-%d %H:%M:%S"));
+            if let (Some(actual), Some(expected)) = (record.timestamp(), expected) {
+                return Some(actual.cmp(&expected));
+            }
+        }
+        _ => {}
+    }
+
+    if let (Ok(actual), Ok(expected)) = (actual.parse::<f64>(), expected.parse::<f64>()) {
+        return actual.partial_cmp(&expected);
+    }
+    Some(actual.cmp(expected))
+}
+
+// Quotes a value if it can't be written as a bare word.
+pub fn quote(value: &str) -> String {
+    let bare = !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_whitespace() || "=!<>~()&|\"'\\".contains(c))
+        && !["and", "or", "not"].contains(&value);
+    if bare {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::And(left, right) => write!(f, "({} && {})", left, right),
+            Self::Or(left, right) => write!(f, "({} || {})", left, right),
+            Self::Not(filter) => write!(f, "!{}", filter),
+            Self::Exists(field) => write!(f, "{}", quote(field)),
+            Self::Compare { field, op, value } => {
+                write!(f, "{} {} {}", quote(field), op.as_str(), quote(value))
+            }
+            Self::Match {
+                field,
+                negated,
+                regex,
+            } => {
+                let op = if *negated { "!~" } else { "~" };
+                write!(f, "{} {} {}", quote(field), op, quote(regex.as_str()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+    use crate::{config::Fields, output::TestRecord};
+
+    #[test]
+    fn filter_matches() {
+        let record = TestRecord::new(&[
+            ("severity", "WARNING"),
+            ("status", "500"),
+            ("path", "/api/users"),
+            ("user", ""),
+        ])
+        .format_fields(Fields {
+            level: Some("severity".to_string()),
+            ..Default::default()
+        });
+        let record = record.record();
+
+        let matches = |filter: &str| Filter::parse(filter).unwrap().matches(&record);
+        assert!(matches("level >= warn && status == 500"));
+        assert!(matches("level < error and (status >= 400 || nope)"));
+        assert!(matches("path ~ '^/api/' && !user && status != 404"));
+        assert!(matches("nope != x"));
+        assert!(!matches("nope == x || path !~ users"));
+        assert!(!matches("status < 60"));
+
+        let filter = Filter::parse("not level == info && \"a b\" == 'say \"hi\"'").unwrap();
+        assert_eq!(
+            "(!level == info && \"a b\" == \"say \\\"hi\\\"\")",
+            filter.to_string()
+        );
+        assert!(Filter::parse("status ==").is_err());
+        assert!(Filter::parse("(status == 1").is_err());
+        assert!(Filter::parse("status == 1 2").is_err());
+        assert!(Filter::parse("status = 1").is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{
+    config::{Config, Example},
+    output::Record,
+    reader::Reader,
+    reader_builder::ReaderBuilder,
+    source::{Source, SourceType},
+};
+use anyhow::bail;
+use std::io::Cursor;
+use tokio::io::BufReader;
+
+// The outcome of one of the `examples` of a format.
+pub struct Outcome {
+    pub format_name: String,
+    // Starting from 1.
+    pub number: usize,
+    // Empty if the example passed.
+    pub failures: Vec<String>,
+}
+
+// Reads the examples of the formats with the given names, every format if there are none.
+pub async fn run(config: Config, names: &[String]) -> anyhow::Result<Vec<Outcome>> {
+    let mut formats: Vec<_> = config
+        .formats
+        .iter()
+        .filter(|(name, _)| names.is_empty() || names.contains(name))
+        .map(|(name, format)| (name.clone(), format.clone()))
+        .collect();
+    formats.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for name in names {
+        if !config.formats.contains_key(name) {
+            bail!("there's no format with name: {}", name);
+        }
+    }
+
+    let builder = ReaderBuilder::new(config)?;
+    let mut outcomes = Vec::new();
+    for (format_name, format) in formats {
+        for (i, example) in format.examples.iter().enumerate() {
+            let source = Source::new(
+                SourceType::File(format!("{} example {}", format_name, i + 1)),
+                BufReader::new(Cursor::new(example.line.clone())),
+            );
+            let reader = builder.find_format(&format_name)?.reader(source);
+            let failures = check(reader, &format_name, example).await;
+            outcomes.push(Outcome {
+                format_name: format_name.clone(),
+                number: i + 1,
+                failures,
+            });
+        }
+    }
+    Ok(outcomes)
+}
+
+async fn check(
+    mut reader: Box<dyn Reader + Send>,
+    format_name: &str,
+    example: &Example,
+) -> Vec<String> {
+    let fields = match reader.read_fields().await {
+        Ok(fields) => fields,
+        Err(e) => return vec![format!("the line can't be read: {}", e)],
+    };
+    let raw = reader.take_raw();
+    let source = reader.source_type();
+    let record = Record {
+        source: &source,
+        label: None,
+        format_name,
+        format_fields: reader.format_fields(),
+        raw: &raw,
+        fields: &fields,
+    };
+
+    let mut failures = Vec::new();
+    for (name, expected) in &example.fields {
+        match record.value(name) {
+            Some(value) if value == expected => {}
+            Some(value) => failures.push(format!(
+                "`{}` is `{}`, expected `{}`",
+                name, value, expected
+            )),
+            None => failures.push(format!("`{}` is missing, expected `{}`", name, expected)),
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn format_examples() {
+        let config = Config::sample();
+        let outcomes = run(config.clone(), &[]).await.unwrap();
+        assert!(!outcomes.is_empty());
+        assert!(outcomes.iter().all(|outcome| outcome.failures.is_empty()));
+
+        let mut config = config;
+        let format = config.formats.get_mut("container_json").unwrap();
+        let example = &mut format.examples[0];
+        example
+            .fields
+            .insert("stream".to_string(), "stderr".to_string());
+        example.fields.insert("nope".to_string(), "1".to_string());
+        let outcomes = run(config, &["container_json".to_string()]).await.unwrap();
+        assert_eq!(
+            vec![
+                "`nope` is missing, expected `1`",
+                "`stream` is `stdout`, expected `stderr`",
+            ],
+            outcomes[0].failures
+        );
+    }
+}
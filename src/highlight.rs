@@ -0,0 +1,206 @@
+use crate::{
+    config::{self, Config},
+    style::Style,
+    theme::Theme,
+};
+use anyhow::{anyhow, bail};
+use regex::Regex;
+use std::collections::HashMap;
+
+// Built-in rules in the order they win when they match at the same place.
+const BUILTIN_RULES: [(&str, &str); 8] = [
+    ("url", r#"\b(?:https?|wss?|ftp)://[^\s"'<>]+"#),
+    (
+        "uuid",
+        r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b",
+    ),
+    (
+        "ip",
+        concat!(
+            r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)(?::\d{1,5})?\b",
+            r"|\b(?:[0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}\b",
+            r"|\b(?:[0-9a-fA-F]{1,4}:){1,6}:(?:[0-9a-fA-F]{1,4}(?::[0-9a-fA-F]{1,4})*)?",
+            r"|::1\b",
+        ),
+    ),
+    // Single quotes only start a string after a non-word character, so `don't` isn't one.
+    (
+        "quoted",
+        r#""(?:[^"\\]|\\.)*"|(?:^|[^\w])(?P<token>'[^'\n]*')"#,
+    ),
+    // `150ms`, `2.5s` or Go's `1h2m3s`
+    ("duration", r"\b(?:\d+(?:\.\d+)?(?:ns|us|µs|ms|s|m|h))+\b"),
+    (
+        "http_method",
+        r"\b(?:GET|HEAD|POST|PUT|DELETE|CONNECT|OPTIONS|TRACE|PATCH)\b",
+    ),
+    // Only after an HTTP version or a `status`, otherwise it's just a number.
+    (
+        "http_status",
+        r#"(?:HTTP/\d(?:\.\d)?"?\s+|\b(?i:status)(?:_code)?[=:]\s*"?)(?P<token>[2-5]\d\d)\b"#,
+    ),
+    ("number", r"\b\d+(?:\.\d+)?\b"),
+];
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    // The built-in rule's name, or None for user-supplied ones.
+    name: Option<&'static str>,
+    regex: Regex,
+    // The theme's style is used when not set.
+    style: Option<Style>,
+}
+
+impl Rule {
+    pub fn custom(pattern: &str, style: Option<&str>) -> anyhow::Result<Self> {
+        let regex = Regex::new(pattern)
+            .map_err(|e| anyhow!("invalid highlight regex `{}`: {}", pattern, e))?;
+        Ok(Self {
+            name: None,
+            regex,
+            style: style.map(Style::parse).transpose()?,
+        })
+    }
+
+    fn builtin(name: &'static str, pattern: &str) -> Self {
+        Self {
+            name: Some(name),
+            regex: Regex::new(pattern).unwrap(),
+            style: None,
+        }
+    }
+
+    fn paint(&self, token: &str, theme: &Theme) -> String {
+        if let Some(style) = &self.style {
+            return style.paint(token);
+        }
+        let key = match self.name {
+            // `highlight.http_status.4xx`
+            Some("http_status") => format!("highlight.http_status.{}xx", &token[..1]),
+            Some(name) => format!("highlight.{}", name),
+            None => "highlight.custom".to_string(),
+        };
+        theme.paint(&key, token)
+    }
+}
+
+// Highlights tokens in messages: URLs, UUIDs, IP addresses, quoted strings, durations,
+// HTTP methods and status codes, numbers and anything the user asks for.
+#[derive(Debug, Clone)]
+pub struct Highlighter {
+    rules: Vec<Rule>,
+}
+
+impl Highlighter {
+    // `custom` rules come first, then the rules of `highlight`, then the built-in ones.
+    pub fn new(highlight: &config::Highlight, custom: &[Rule]) -> anyhow::Result<Self> {
+        let mut rules = custom.to_vec();
+        for rule in &highlight.rules {
+            rules.push(Rule::custom(&rule.pattern, rule.style.as_deref())?);
+        }
+
+        for name in &highlight.disable {
+            if !BUILTIN_RULES.iter().any(|(builtin, _)| builtin == name) {
+                bail!(
+                    "unknown highlight rule `{}`. built-in rules: {}",
+                    name,
+                    BUILTIN_RULES.map(|(name, _)| name).join(", ")
+                );
+            }
+        }
+        for (name, pattern) in BUILTIN_RULES {
+            if !highlight.disable.iter().any(|disabled| disabled == name) {
+                rules.push(Rule::builtin(name, pattern));
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    pub fn highlight(&self, text: &str, theme: &Theme) -> String {
+        // (start, end, rule) of every match, the highlighted ones are picked below.
+        let mut matches = Vec::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            for captures in rule.regex.captures_iter(text) {
+                let token = captures.name("token").or_else(|| captures.get(0)).unwrap();
+                if token.start() < token.end() {
+                    matches.push((token.start(), token.end(), i));
+                }
+            }
+        }
+        // The earliest match wins, then the one of the earlier rule.
+        matches.sort_by_key(|(start, _, rule)| (*start, *rule));
+
+        let mut out = String::with_capacity(text.len());
+        let mut position = 0;
+        for (start, end, rule) in matches {
+            if start < position {
+                continue;
+            }
+            let token = &text[start..end];
+            out.push_str(&text[position..start]);
+            out.push_str(&self.rules[rule].paint(token, theme));
+            position = end;
+        }
+        out.push_str(&text[position..]);
+        out
+    }
+}
+
+// Highlighters of every format, each with the user-supplied rules.
+pub struct Highlighters {
+    formats: HashMap<String, Highlighter>,
+    default: Highlighter,
+}
+
+impl Highlighters {
+    pub fn new(config: &Config, custom: &[Rule]) -> anyhow::Result<Self> {
+        let mut formats = HashMap::new();
+        for (format_name, format) in &config.formats {
+            let highlighter = Highlighter::new(&format.highlight, custom)
+                .map_err(|e| anyhow!("format `{}`: {}", format_name, e))?;
+            formats.insert(format_name.clone(), highlighter);
+        }
+        Ok(Self {
+            formats,
+            default: Highlighter::new(&config::Highlight::default(), custom)?,
+        })
+    }
+
+    pub fn get(&self, format_name: &str) -> &Highlighter {
+        self.formats.get(format_name).unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Highlighter, Rule};
+    use crate::{config::Highlight, theme::Theme};
+
+    #[test]
+    fn highlight_tokens() {
+        let highlight = Highlight {
+            disable: vec!["number".to_string()],
+            rules: Vec::new(),
+        };
+        let custom = [Rule::custom("user=(?P<token>\\w+)", Some("bold")).unwrap()];
+        let highlighter = Highlighter::new(&highlight, &custom).unwrap();
+
+        let highlighted = highlighter.highlight(
+            "GET http://x.io/a?id=1 from 10.0.0.1 user=bob took 1.5ms: 'a b' HTTP/1.1\" 404 42",
+            &Theme::builtin("dark").unwrap(),
+        );
+        assert_eq!(
+            "\x1b[1mGET\x1b[0m \x1b[4;34mhttp://x.io/a?id=1\x1b[0m from \x1b[34m10.0.0.1\x1b[0m \
+            user=\x1b[1mbob\x1b[0m took \x1b[33m1.5ms\x1b[0m: \x1b[32m'a b'\x1b[0m HTTP/1.1\" \
+            \x1b[33m404\x1b[0m 42",
+            highlighted
+        );
+
+        let highlight = Highlight {
+            disable: vec!["nope".to_string()],
+            rules: Vec::new(),
+        };
+        assert!(Highlighter::new(&highlight, &[]).is_err());
+    }
+}
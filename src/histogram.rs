@@ -0,0 +1,369 @@
+use crate::{
+    level::Level,
+    output::{Output, Record},
+    stats::group_value,
+    style::Style,
+    theme::Theme,
+    timestamp,
+};
+use chrono::{FixedOffset, TimeDelta};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+// Series after this many are drawn together as `other`.
+const MAX_SERIES: usize = 8;
+// What series are drawn with when there are no colors to tell them apart.
+const BAR_CHARS: [char; MAX_SERIES + 1] = ['█', '▓', '▒', '░', '#', '=', '+', ':', '.'];
+// Colors of series that aren't levels.
+const PALETTE: [&str; MAX_SERIES + 1] = [
+    "blue",
+    "green",
+    "yellow",
+    "magenta",
+    "cyan",
+    "red",
+    "bright_blue",
+    "bright_green",
+    "gray",
+];
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const OTHER: &str = "other";
+
+pub struct HistogramOptions {
+    // Widened to a multiple when there would be more than MAX_BUCKETS bars.
+    pub bucket: TimeDelta,
+    // Bars are stacked by the values of this field.
+    pub by: Option<String>,
+    // One line per series instead of a bar per bucket.
+    pub sparkline: bool,
+    // Set if colors are used.
+    pub theme: Option<Theme>,
+    // Terminal width.
+    pub width: usize,
+}
+
+// Counts records per bucket of time and draws them as a bar chart at the end.
+pub struct HistogramOutput {
+    writer: Box<dyn Write + Send>,
+    options: HistogramOptions,
+    // Counts by bucket index, then by the value of the `by` field.
+    buckets: BTreeMap<i64, HashMap<String, u64>>,
+    totals: HashMap<String, u64>,
+    // Buckets are shown in the time zone of the first record.
+    zone: Option<FixedOffset>,
+    // Records without a timestamp.
+    skipped: u64,
+}
+
+struct Series {
+    name: String,
+    total: u64,
+    style: Style,
+    bar: char,
+}
+
+impl HistogramOutput {
+    pub fn new(writer: Box<dyn Write + Send>, options: HistogramOptions) -> Self {
+        Self {
+            writer,
+            options,
+            buckets: BTreeMap::new(),
+            totals: HashMap::new(),
+            zone: None,
+            skipped: 0,
+        }
+    }
+
+    // The series to draw in stacking order: levels from least to most severe, anything else
+    // from the most to the least common.
+    fn series(&self) -> Vec<Series> {
+        let mut names: Vec<_> = self.totals.iter().collect();
+        names.sort_by_key(|(name, total)| (Reverse(**total), name.to_string()));
+        if self.options.by.as_deref() == Some("level") {
+            names.sort_by_key(|(name, _)| Level::parse(name).map_or(0, |level| level as u8 + 1));
+        }
+
+        let mut series: Vec<_> = names
+            .iter()
+            .take(if names.len() > MAX_SERIES {
+                MAX_SERIES - 1
+            } else {
+                MAX_SERIES
+            })
+            .enumerate()
+            .map(|(i, (name, total))| Series {
+                name: name.to_string(),
+                total: **total,
+                style: self.style(name, i),
+                bar: BAR_CHARS[i],
+            })
+            .collect();
+        if names.len() > series.len() {
+            series.push(Series {
+                name: OTHER.to_string(),
+                total: names[series.len()..].iter().map(|(_, total)| **total).sum(),
+                style: self.style(OTHER, MAX_SERIES),
+                bar: BAR_CHARS[MAX_SERIES],
+            });
+        }
+        series
+    }
+
+    fn style(&self, name: &str, i: usize) -> Style {
+        match (&self.options.theme, Level::parse(name)) {
+            (None, _) => Style::default(),
+            (Some(theme), Some(level)) if self.options.by.as_deref() == Some("level") => {
+                theme.level(level).clone()
+            }
+            _ => Style::parse(PALETTE[i]).unwrap(),
+        }
+    }
+
+    // Counts of every bucket from the first to the last one, by series, along with the
+    // bucket's label.
+    fn rows(&self, series: &[Series]) -> Vec<(String, Vec<u64>)> {
+        let (Some((&first, _)), Some((&last, _))) = (
+            self.buckets.first_key_value(),
+            self.buckets.last_key_value(),
+        ) else {
+            return Vec::new();
+        };
+        let range = timestamp::BucketRange::new(first, last, self.options.bucket);
+        let mut rows: BTreeMap<i64, Vec<u64>> = range
+            .indexes()
+            .map(|index| (index, vec![0; series.len()]))
+            .collect();
+        for (&index, names) in &self.buckets {
+            let counts = rows.get_mut(&range.widen(index)).unwrap();
+            for (name, count) in names {
+                let i = series
+                    .iter()
+                    .position(|series| &series.name == name)
+                    .unwrap_or(series.len() - 1);
+                counts[i] += count;
+            }
+        }
+        rows.into_iter()
+            .map(|(index, counts)| (self.label(index, range.interval), counts))
+            .collect()
+    }
+
+    fn label(&self, index: i64, bucket: TimeDelta) -> String {
+        let zone = self.zone.unwrap_or(FixedOffset::east_opt(0).unwrap());
+        let millis = bucket.num_milliseconds();
+        let format = if millis % (24 * 60 * 60 * 1000) == 0 {
+            "%Y-%m-%d"
+        } else if millis % (60 * 1000) == 0 {
+            "%Y-%m-%d %H:%M"
+        } else if millis % 1000 == 0 {
+            "%Y-%m-%d %H:%M:%S"
+        } else {
+            "%Y-%m-%d %H:%M:%S%.3f"
+        };
+        timestamp::bucket_start(index, bucket, &zone)
+            .map(|start| start.format(format).to_string())
+            .unwrap_or_default()
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let series = self.series();
+        let rows = self.rows(&series);
+        if rows.is_empty() {
+            return Vec::new();
+        }
+        let mut lines = Vec::new();
+
+        if self.options.by.is_some() {
+            let legend: Vec<_> = series
+                .iter()
+                .map(|series| {
+                    let key = format!("{} {}", series.bar, series.name);
+                    format!("{} {}", series.style.paint(&key), series.total)
+                })
+                .collect();
+            lines.push(legend.join("  "));
+        }
+
+        if self.options.sparkline {
+            lines.extend(self.sparklines(&series, &rows));
+        } else {
+            lines.extend(self.bars(&series, &rows));
+        }
+        lines
+    }
+
+    fn bars(&self, series: &[Series], rows: &[(String, Vec<u64>)]) -> Vec<String> {
+        let max = rows
+            .iter()
+            .map(|(_, counts)| counts.iter().sum::<u64>())
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let label_width = rows[0].0.chars().count();
+        let count_width = max.to_string().len();
+        let width = self
+            .options
+            .width
+            .saturating_sub(label_width + count_width + 4)
+            .max(10);
+
+        rows.iter()
+            .map(|(label, counts)| {
+                // Each series ends where the running total does, so that rounding doesn't
+                // make the stack longer than its total.
+                let mut bar = String::new();
+                let mut total = 0;
+                let mut drawn = 0;
+                for (series, count) in series.iter().zip(counts) {
+                    total += count;
+                    let end = (total * width as u64).div_ceil(max) as usize;
+                    if end > drawn {
+                        let part: String = std::iter::repeat_n(series.bar, end - drawn).collect();
+                        bar.push_str(&series.style.paint(&part));
+                        drawn = end;
+                    }
+                }
+                let padding = " ".repeat(width - drawn);
+                format!("{} │{}{} {:>4$}", label, bar, padding, total, count_width)
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn sparklines(&self, series: &[Series], rows: &[(String, Vec<u64>)]) -> Vec<String> {
+        let plain = Style::default();
+        // Without `by`, the only series is the total.
+        let lines: Vec<(&str, &Style, Vec<u64>)> = match self.options.by {
+            Some(_) => series
+                .iter()
+                .enumerate()
+                .map(|(i, series)| {
+                    let counts = rows.iter().map(|(_, counts)| counts[i]).collect();
+                    (series.name.as_str(), &series.style, counts)
+                })
+                .collect(),
+            None => {
+                let counts = rows.iter().map(|(_, counts)| counts.iter().sum()).collect();
+                vec![("records", &plain, counts)]
+            }
+        };
+        let name_width = lines
+            .iter()
+            .map(|(name, _, _)| name.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let mut out: Vec<String> = lines
+            .into_iter()
+            .map(|(name, style, counts)| {
+                let max = counts.iter().copied().max().unwrap_or(0).max(1);
+                let spark: String = counts
+                    .iter()
+                    .map(|&count| match count {
+                        0 => ' ',
+                        count => SPARKS[(count * SPARKS.len() as u64).div_ceil(max) as usize - 1],
+                    })
+                    .collect();
+                format!(
+                    "{:<name_width$}  {}  max {}",
+                    name,
+                    style.paint(&spark),
+                    max
+                )
+            })
+            .collect();
+        out.push(format!(
+            "{:<name_width$}  {} – {}",
+            "",
+            rows[0].0,
+            rows[rows.len() - 1].0
+        ));
+        out
+    }
+}
+
+impl Output for HistogramOutput {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let Some(time) = record.timestamp() else {
+            self.skipped += 1;
+            return Ok(());
+        };
+        self.zone.get_or_insert(*time.offset());
+        let name = match &self.options.by {
+            Some(by) => group_value(record, by),
+            None => String::new(),
+        };
+        *self
+            .buckets
+            .entry(timestamp::bucket(time, self.options.bucket))
+            .or_default()
+            .entry(name.clone())
+            .or_insert(0) += 1;
+        *self.totals.entry(name).or_insert(0) += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.skipped > 0 {
+            eprintln!("Left out {} records without a timestamp", self.skipped);
+        }
+        for line in self.lines() {
+            writeln!(self.writer, "{}", line)?;
+        }
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HistogramOptions, HistogramOutput};
+    use crate::output::{Output, TestRecord};
+    use chrono::TimeDelta;
+
+    #[test]
+    fn histogram_lines() {
+        let options = HistogramOptions {
+            bucket: TimeDelta::minutes(1),
+            by: Some("level".to_string()),
+            sparkline: false,
+            theme: None,
+            width: 40,
+        };
+        let mut histogram = HistogramOutput::new(Box::new(std::io::sink()), options);
+        for (ts, level) in [
+            ("2024-01-01T10:00:10Z", "info"),
+            ("2024-01-01T10:00:20Z", "info"),
+            ("2024-01-01T10:00:30Z", "error"),
+            ("2024-01-01T10:02:00Z", "INFO"),
+        ] {
+            let record =
+                TestRecord::new(&[("ts", ts), ("level", level)]).timestamp("ts", "rfc3339");
+            histogram.write(&record.record()).unwrap();
+        }
+
+        assert_eq!(
+            vec![
+                "█ INFO 3  ▓ ERROR 1",
+                "2024-01-01 10:00 │█████████████▓▓▓▓▓▓ 3",
+                "2024-01-01 10:01 │                    0",
+                "2024-01-01 10:02 │███████             1",
+            ],
+            histogram.lines()
+        );
+
+        histogram.options.sparkline = true;
+        assert_eq!(
+            vec![
+                "█ INFO 3  ▓ ERROR 1",
+                "INFO   █ ▄  max 2",
+                "ERROR  █    max 1",
+                "       2024-01-01 10:00 – 2024-01-01 10:02",
+            ],
+            histogram.lines()
+        );
+    }
+}
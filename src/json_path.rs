@@ -0,0 +1,160 @@
+use anyhow::bail;
+use serde_json::Value;
+use std::fmt::Display;
+
+// A small JSONPath-like selector. Supported syntax:
+// `$.http.status`, `http.status`, `items[0].id` and `$["key.with.dots"]`.
+// The leading `$` is optional.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = path.trim();
+        rest = rest.strip_prefix('$').unwrap_or(rest);
+
+        let mut first = true;
+        while !rest.is_empty() {
+            if let Some(r) = rest.strip_prefix('[') {
+                let end = match r.find(']') {
+                    Some(end) => end,
+                    None => bail!("invalid path `{}`: unclosed `[`", path),
+                };
+                let inner = r[..end].trim();
+                if let Some(key) = unquote(inner) {
+                    segments.push(Segment::Key(key.to_string()));
+                } else {
+                    match inner.parse::<usize>() {
+                        Ok(index) => segments.push(Segment::Index(index)),
+                        Err(_) => bail!("invalid path `{}`: bad index `{}`", path, inner),
+                    }
+                }
+                rest = &r[end + 1..];
+            } else {
+                if let Some(r) = rest.strip_prefix('.') {
+                    rest = r;
+                } else if !first {
+                    bail!("invalid path `{}`: expected `.` or `[`", path);
+                }
+                let end = rest.find(['.', '[']).unwrap_or(rest.len());
+                if end == 0 {
+                    bail!("invalid path `{}`: empty key", path);
+                }
+                segments.push(Segment::Key(rest[..end].to_string()));
+                rest = &rest[end..];
+            }
+            first = false;
+        }
+
+        if segments.is_empty() {
+            bail!("invalid path `{}`: it selects nothing", path);
+        }
+
+        Ok(Self { segments })
+    }
+
+    pub fn select<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        let mut value = value;
+        for segment in &self.segments {
+            value = match segment {
+                Segment::Key(key) => value.as_object()?.get(key)?,
+                Segment::Index(index) => value.as_array()?.get(*index)?,
+            };
+        }
+        Some(value)
+    }
+
+    // The key of the top-level field the path selects inside, if it selects a nested value.
+    pub fn parent_key(&self) -> Option<&str> {
+        match self.segments.as_slice() {
+            [Segment::Key(key), _, ..] => Some(key),
+            _ => None,
+        }
+    }
+
+    // Removes the selected value from `value`. Returns false if nothing was selected.
+    pub fn remove(&self, value: &mut Value) -> bool {
+        let (last, parents) = match self.segments.split_last() {
+            Some(split) => split,
+            None => return false,
+        };
+
+        let mut value = value;
+        for segment in parents {
+            let next = match segment {
+                Segment::Key(key) => value.as_object_mut().and_then(|o| o.get_mut(key)),
+                Segment::Index(index) => value.as_array_mut().and_then(|a| a.get_mut(*index)),
+            };
+            value = match next {
+                Some(next) => next,
+                None => return false,
+            };
+        }
+
+        match last {
+            Segment::Key(key) => value
+                .as_object_mut()
+                .map(|o| o.remove(key).is_some())
+                .unwrap_or(false),
+            Segment::Index(index) => match value.as_array_mut() {
+                Some(a) if *index < a.len() => {
+                    a.remove(*index);
+                    true
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+// The field name of a selected value. This is also the key used when flattening,
+// so `$.http.status` and a flattened `{"http":{"status":500}}` agree on `http.status`.
+impl Display for JsonPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Key(key) if i == 0 => write!(f, "{}", key)?,
+                Segment::Key(key) => write!(f, ".{}", key)?,
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unquote(s: &str) -> Option<&str> {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .or_else(|| s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')))
+}
+
+// Calls `f` with the dotted name and value of every leaf under `value`.
+pub fn flatten(prefix: &str, value: &Value, f: &mut impl FnMut(String, &Value)) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (k, v) in map {
+                let name = if prefix.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", prefix, k)
+                };
+                flatten(&name, v, f);
+            }
+        }
+        Value::Array(array) if !array.is_empty() => {
+            for (i, v) in array.iter().enumerate() {
+                flatten(&format!("{}[{}]", prefix, i), v, f);
+            }
+        }
+        _ => f(prefix.to_string(), value),
+    }
+}
@@ -0,0 +1,32 @@
+use crate::field::{Field, FieldMap};
+use regex::Regex;
+use std::path::Path;
+
+// Extracts pod metadata from the file names kubelet creates under /var/log/containers:
+// `<pod>_<namespace>_<container>-<container_id>.log`
+pub fn metadata_from_path(path: &str) -> FieldMap {
+    let mut map = FieldMap::new();
+
+    let file_name = match Path::new(path).file_name().and_then(|name| name.to_str()) {
+        Some(file_name) => file_name,
+        None => return map,
+    };
+
+    let re = Regex::new(
+        r"^(?P<pod>[^_]+)_(?P<namespace>[^_]+)_(?P<container>.+)-(?P<container_id>[0-9a-f]{64})\.log$",
+    )
+    .unwrap();
+
+    if let Some(caps) = re.captures(file_name) {
+        for name in ["pod", "namespace", "container", "container_id"] {
+            if let Some(cap) = caps.name(name) {
+                map.insert(
+                    name.to_string(),
+                    Field::new(name.to_string(), cap.as_str().to_string()),
+                );
+            }
+        }
+    }
+
+    map
+}
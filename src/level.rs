@@ -0,0 +1,38 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    // Understands common level names and abbreviations, and syslog priorities (0-7)
+    // as found in the journal's PRIORITY field.
+    pub fn parse(value: &str) -> Option<Self> {
+        let level = match value.trim().to_ascii_lowercase().as_str() {
+            "trace" | "trc" => Self::Trace,
+            "debug" | "dbg" | "7" => Self::Debug,
+            "info" | "inf" | "information" | "notice" | "5" | "6" => Self::Info,
+            "warn" | "wrn" | "warning" | "4" => Self::Warn,
+            "error" | "err" | "eror" | "3" => Self::Error,
+            "fatal" | "ftl" | "critical" | "crit" | "panic" | "alert" | "emerg" | "0" | "1"
+            | "2" => Self::Fatal,
+            _ => return None,
+        };
+        Some(level)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Trace => "TRACE",
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO",
+            Self::Warn => "WARN",
+            Self::Error => "ERROR",
+            Self::Fatal => "FATAL",
+        }
+    }
+}
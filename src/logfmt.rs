@@ -0,0 +1,87 @@
+use anyhow::bail;
+
+// Parses a logfmt line such as `level=info msg="hello world" retry`.
+// A key without `=` gets an empty value.
+pub fn parse(line: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut pairs = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && !c.is_whitespace()) {
+            key.push(c);
+        }
+        if key.is_empty() {
+            bail!("logfmt: expected a key");
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some('r') => value.push('\r'),
+                            Some(c) => value.push(c),
+                            None => bail!("logfmt: unterminated escape in `{}`", key),
+                        },
+                        Some(c) => value.push(c),
+                        None => bail!("logfmt: unterminated quote in `{}`", key),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    value.push(c);
+                }
+            }
+        }
+
+        pairs.push((key, value));
+    }
+
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn logfmt_parse() {
+        let pairs =
+            parse(r#" level=info msg="hello \"world\"\n\tend" retry path=/a=b empty= "#).unwrap();
+        let expected = [
+            ("level", "info"),
+            ("msg", "hello \"world\"\n\tend"),
+            ("retry", ""),
+            ("path", "/a=b"),
+            ("empty", ""),
+        ];
+        let expected: Vec<(String, String)> = expected
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(expected, pairs);
+
+        assert_eq!(
+            vec![("quoted".to_string(), "a b".to_string())],
+            parse(r#"quoted="a b""#).unwrap()
+        );
+        assert!(parse("").unwrap().is_empty());
+
+        let err = parse(r#"a=1 msg="hello"#).unwrap_err();
+        assert_eq!("logfmt: unterminated quote in `msg`", err.to_string());
+        let err = parse(r#"msg="hello\"#).unwrap_err();
+        assert_eq!("logfmt: unterminated escape in `msg`", err.to_string());
+        let err = parse("=value").unwrap_err();
+        assert_eq!("logfmt: expected a key", err.to_string());
+    }
+}
@@ -1,62 +1,435 @@
 mod config;
+mod config_check;
+mod config_watch;
+mod context;
 mod field;
+mod filter;
+mod format_examples;
+mod highlight;
+mod histogram;
+mod json_path;
+mod kubernetes;
+mod level;
+mod logfmt;
+mod output;
+mod output_csv;
+mod output_json;
+mod output_logfmt;
+mod output_pretty;
+mod output_raw;
+mod output_table;
+mod pager;
 mod reader;
 mod reader_builder;
+mod reader_cri;
+mod reader_csv;
+mod reader_docker;
+mod reader_journal;
 mod reader_json;
 mod reader_regex;
 mod source;
+mod stacktrace;
+mod stats;
+mod style;
+mod template;
+mod theme;
+mod timestamp;
 
-use crate::config::Config;
-use crate::reader_builder::ReaderBuilder;
+use crate::config::{Config, Overrides};
+use crate::config_watch::Reloads;
+use crate::context::{Emit, Selection, Window};
+use crate::field::FieldMap;
+use crate::filter::Filter;
+use crate::highlight::Highlighters;
+use crate::histogram::{HistogramOptions, HistogramOutput};
+use crate::output_table::Column;
+use crate::pager::{Pager, PagerOptions, PagerOutput, SharedStore};
+use crate::reader_builder::{Input, ReaderBuilder, SourceSpec};
+use crate::stats::{StatsOptions, StatsOutput, STATS_FORMATS};
+use crate::style::ColorDepth;
+use crate::template::Template;
+use crate::theme::Theme;
+use crate::timestamp::{Mode, TimeOptions, Zone};
 use anyhow::{anyhow, bail};
-use clap::{arg, crate_version, ArgAction, Command};
+use chrono::format::{Item, StrftimeItems};
+use clap::{arg, crate_version, Arg, ArgAction, ArgMatches, Command};
 use futures::{
     channel::mpsc::{channel, Receiver},
     future::join_all,
     SinkExt, StreamExt,
 };
 use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
-use reader::{ReadError, Reader};
+use output::{OutputOptions, Record, SharedOutput, OUTPUT_FORMATS};
+use reader::ReadError;
+use regex::Regex;
 use source::SourceType;
-use std::{path::Path, time::Duration};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let matches = Command::new("falog")
-        .arg(arg!([source] "Files to operate on").action(ArgAction::Append))
-        .arg(arg!(-f --follow "Print logs as they are appended. Works only on files. Usage is redundant with stdin input.").action(ArgAction::SetTrue))
-        .version(crate_version!())
-        .get_matches();
+    let matches = cli().get_matches();
 
-    let config = ::config::Config::builder()
-        .add_source(::config::File::with_name("./falog.yml"))
-        .build()?;
+    let explicit = matches
+        .get_one::<String>("config")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("FALOG_CONFIG").map(PathBuf::from));
+    let sets: Vec<String> = matches
+        .get_many::<String>("set")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let overrides = Overrides::new(std::env::vars_os(), &sets)?;
+    for name in &overrides.skipped {
+        eprintln!("Warning: {} isn't a config key, left out", name);
+    }
+    // Checked before loading, which stops at the first problem.
+    if let Some(("config", matches)) = matches.subcommand() {
+        if let Some(("check", _)) = matches.subcommand() {
+            return check_config(explicit.as_deref(), &overrides);
+        }
+    }
+    let config = Config::load(explicit.as_deref(), &overrides)?;
 
-    let config: Config = config.try_deserialize()?;
+    match matches.subcommand() {
+        Some(("stats", matches)) => return stats(matches, config).await,
+        Some(("histogram", matches)) => return histogram(matches, config).await,
+        Some(("test-formats", matches)) => return test_formats(matches, config).await,
+        _ => {}
+    }
+    if matches.get_flag("interactive") {
+        return interactive(&matches, config, explicit, overrides).await;
+    }
 
     let follow = *matches.get_one::<bool>("follow").unwrap_or(&false);
+    let mut output_format = matches.get_one::<String>("output").unwrap().as_str();
+    let template = matches
+        .get_one::<String>("template")
+        .map(|template| Template::parse(template))
+        .transpose()?;
+    let time = time_options(&matches)?;
+    let (before, after) = context(&matches);
+    let selection = selection(&matches, before, after)?.map(Arc::new);
+    let columns = match matches.get_one::<String>("columns") {
+        Some(columns) => {
+            output_format = "table";
+            output_table::parse_columns(columns)?
+        }
+        None => output_table::parse_columns(output_table::DEFAULT_COLUMNS)?,
+    };
 
-    let mut futs = vec![];
-    let readers;
+    let highlight_rules = matches
+        .get_many::<String>("highlight")
+        .unwrap_or_default()
+        .map(|pattern| highlight::Rule::custom(pattern, None))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let highlighters = Highlighters::new(&config, &highlight_rules)?;
+    let stacktrace = config.stacktrace.clone();
+    let theme = Theme::from_config(&config.theme)?;
+    let color = color(&matches);
     let mut reader_builder = ReaderBuilder::new(config)?;
+    let inputs = build_inputs(&mut reader_builder, &matches).await?;
 
-    if let Some(sources) = matches.get_many::<String>("source") {
-        let sources = sources.map(|source| source.to_string()).collect();
-        readers = reader_builder.build(sources).await?;
+    if let Some(template) = &template {
+        for input in &inputs {
+            template
+                .validate(
+                    input.reader.format_fields(),
+                    reader_builder.known_fields(&input.format_name),
+                )
+                .map_err(|e| anyhow!("--template: format `{}`: {}", input.format_name, e))?;
+        }
+    }
+    if output_format == "table" {
+        validate_columns(&columns, &inputs, &reader_builder)?;
+    }
+
+    let options = OutputOptions {
+        multiple_sources: inputs.len() > 1,
+        color,
+        theme,
+        template,
+        format_templates: reader_builder.templates(),
+        columns,
+        stacktrace,
+        highlighters,
+        time,
+    };
+    let output: SharedOutput = Arc::new(Mutex::new(output::new_output(output_format, options)));
+
+    let reloads = if follow {
+        Some(config_watch::watch(
+            explicit,
+            overrides,
+            highlight_rules,
+            Arc::new(reader_builder),
+            output.clone(),
+        )?)
     } else {
-        readers = reader_builder.build(vec![]).await?;
+        None
+    };
+    read_inputs(inputs, &output, selection, follow, reloads).await
+}
+
+// `falog config check`: prints every problem of the config files, and of the keys set with
+// FALOG_ variables and `--set`.
+fn check_config(explicit: Option<&Path>, overrides: &Overrides) -> anyhow::Result<()> {
+    let files = config_check::files(explicit);
+    let mut problems = config_check::check(&files);
+    let mut names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+    if !overrides.is_empty() {
+        // Loading reports what's wrong with the files too, so it's only tried once they're fine.
+        if problems.is_empty() {
+            problems.extend(config_check::check_overrides(explicit, overrides));
+        }
+        names.push(config_check::OVERRIDES);
+    }
+    for problem in &problems {
+        println!("{}", problem);
     }
+    match problems.len() {
+        0 => {
+            println!("{}: OK", names.join(", "));
+            Ok(())
+        }
+        1 => bail!("found a problem in the config"),
+        count => bail!("found {} problems in the config", count),
+    }
+}
+
+// `falog test-formats`: reads the `examples` of formats and prints the ones that don't read
+// as expected.
+async fn test_formats(matches: &ArgMatches, config: Config) -> anyhow::Result<()> {
+    let names: Vec<String> = matches
+        .get_many::<String>("format")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let outcomes = format_examples::run(config, &names).await?;
+
+    let mut failed = 0;
+    for outcome in &outcomes {
+        if outcome.failures.is_empty() {
+            println!("{} example {} ... ok", outcome.format_name, outcome.number);
+            continue;
+        }
+        failed += 1;
+        println!(
+            "{} example {} ... FAILED",
+            outcome.format_name, outcome.number
+        );
+        for failure in &outcome.failures {
+            println!("    {}", failure);
+        }
+    }
+    println!();
+    println!("{} passed, {} failed", outcomes.len() - failed, failed);
+    if failed > 0 {
+        bail!("{} of {} format examples failed", failed, outcomes.len());
+    }
+    Ok(())
+}
 
-    if readers.len() == 0 {
+// `falog -i`: shows the records in a pager while they're read in the background.
+async fn interactive(
+    matches: &ArgMatches,
+    config: Config,
+    explicit: Option<PathBuf>,
+    overrides: Overrides,
+) -> anyhow::Result<()> {
+    if !atty::is(atty::Stream::Stdout) {
+        bail!("-i needs stdout to be a terminal");
+    }
+    let columns = output_table::parse_columns(
+        matches
+            .get_one::<String>("columns")
+            .map_or(output_table::DEFAULT_COLUMNS, String::as_str),
+    )?;
+    let theme = if color(matches) {
+        Theme::from_config(&config.theme)?
+    } else {
+        Theme::builtin("none").unwrap()
+    };
+    let options = PagerOptions {
+        columns,
+        filter: matches.get_one::<String>("filter").cloned(),
+        follow: matches.get_flag("follow"),
+        theme,
+        time: time_options(matches)?,
+        stacktrace: config.stacktrace.clone(),
+    };
+    let store = SharedStore::default();
+    let pager = Pager::new(store.clone(), options)?;
+
+    let mut reader_builder = ReaderBuilder::new(config)?;
+    let inputs = build_inputs(&mut reader_builder, matches).await?;
+    validate_columns(pager.columns(), &inputs, &reader_builder)?;
+
+    let output: SharedOutput = Arc::new(Mutex::new(Box::new(PagerOutput::new(store.clone()))));
+    let reloads = config_watch::watch(
+        explicit,
+        overrides,
+        Vec::new(),
+        Arc::new(reader_builder),
+        output.clone(),
+    )?;
+    // Files are always followed, `-f` only makes the pager stick to the last record.
+    tokio::spawn(async move {
+        if let Err(e) = read_inputs(inputs, &output, None, true, Some(reloads)).await {
+            store.lock().unwrap().set_error(e.to_string());
+        }
+    });
+    tokio::task::spawn_blocking(move || pager::run(pager)).await??;
+
+    // Readers may be blocked reading stdin, don't wait for them.
+    std::process::exit(0);
+}
+
+fn validate_columns(
+    columns: &[Column],
+    inputs: &[Input],
+    reader_builder: &ReaderBuilder,
+) -> anyhow::Result<()> {
+    for input in inputs {
+        for column in columns {
+            column
+                .template()
+                .validate(
+                    input.reader.format_fields(),
+                    reader_builder.known_fields(&input.format_name),
+                )
+                .map_err(|e| anyhow!("--columns: format `{}`: {}", input.format_name, e))?;
+        }
+    }
+    Ok(())
+}
+
+// `falog stats`: reads every input to the end and writes a summary of the records.
+async fn stats(matches: &ArgMatches, config: Config) -> anyhow::Result<()> {
+    let selection = selection(matches, None, None)?.map(Arc::new);
+    let groups = matches
+        .get_many::<String>("by")
+        .unwrap_or_default()
+        .map(|fields| split_fields(fields, "--by"))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let numeric = matches
+        .get_many::<String>("field")
+        .unwrap_or_default()
+        .map(|fields| split_fields(fields, "--field"))
+        .collect::<anyhow::Result<Vec<_>>>()?
+        .concat();
+    let interval = matches
+        .get_one::<String>("interval")
+        .map(|interval| {
+            timestamp::parse_duration(interval).map_err(|e| anyhow!("--interval: {}", e))
+        })
+        .transpose()?;
+    let options = StatsOptions {
+        groups,
+        top: *matches.get_one::<usize>("top").unwrap(),
+        numeric,
+        interval,
+        json: matches.get_one::<String>("output").unwrap() == "json",
+    };
+
+    let mut reader_builder = ReaderBuilder::new(config)?;
+    let inputs = build_inputs(&mut reader_builder, matches).await?;
+    let output: SharedOutput = Arc::new(Mutex::new(Box::new(StatsOutput::new(
+        Box::new(io::stdout()),
+        options,
+    ))));
+    read_inputs(inputs, &output, selection, false, None).await
+}
+
+// `falog histogram`: reads every input to the end and draws record counts over time.
+async fn histogram(matches: &ArgMatches, config: Config) -> anyhow::Result<()> {
+    let selection = selection(matches, None, None)?.map(Arc::new);
+    let bucket = timestamp::parse_duration(matches.get_one::<String>("bucket").unwrap())
+        .map_err(|e| anyhow!("--bucket: {}", e))?;
+    let theme = Theme::from_config(&config.theme)?;
+    let width = terminal_size::terminal_size()
+        .map(|(terminal_size::Width(width), _)| width as usize)
+        .unwrap_or(80);
+    let options = HistogramOptions {
+        bucket,
+        by: matches.get_one::<String>("by").cloned(),
+        sparkline: matches.get_flag("sparkline"),
+        theme: color(matches).then_some(theme),
+        width,
+    };
+
+    let mut reader_builder = ReaderBuilder::new(config)?;
+    let inputs = build_inputs(&mut reader_builder, matches).await?;
+    let output: SharedOutput = Arc::new(Mutex::new(Box::new(HistogramOutput::new(
+        Box::new(io::stdout()),
+        options,
+    ))));
+    read_inputs(inputs, &output, selection, false, None).await
+}
+
+// Whether to use colors, as asked with `--color`. Also sets how many colors there are.
+fn color(matches: &ArgMatches) -> bool {
+    style::set_color_depth(ColorDepth::detect());
+    match matches.get_one::<String>("color").unwrap().as_str() {
+        "always" => true,
+        "never" => false,
+        // https://no-color.org
+        _ => {
+            atty::is(atty::Stream::Stdout)
+                && std::env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
+        }
+    }
+}
+
+// Comma separated field names, e.g. `method,status`.
+fn split_fields(fields: &str, arg: &str) -> anyhow::Result<Vec<String>> {
+    let fields: Vec<_> = fields
+        .split(',')
+        .map(|field| field.trim().to_string())
+        .collect();
+    if fields.iter().any(String::is_empty) {
+        bail!("{}: empty field name in `{}`", arg, fields.join(","));
+    }
+    Ok(fields)
+}
+
+async fn build_inputs(
+    reader_builder: &mut ReaderBuilder,
+    matches: &ArgMatches,
+) -> anyhow::Result<Vec<Input>> {
+    let inputs = reader_builder
+        .build(source_specs(reader_builder, matches)?)
+        .await?;
+
+    if inputs.is_empty() {
         bail!("No files are given as argument and there is no input on stdin.");
     }
+    Ok(inputs)
+}
 
-    for reader in readers {
-        let source_type = reader.source_type();
+// Reads every input concurrently into `output`, following files if `follow` is set. Inputs
+// switch to the formats of a reloaded config if `reloads` is set.
+async fn read_inputs(
+    inputs: Vec<Input>,
+    output: &SharedOutput,
+    selection: Option<Arc<Selection>>,
+    follow: bool,
+    reloads: Option<Reloads>,
+) -> anyhow::Result<()> {
+    let mut futs = vec![];
+    for input in inputs {
+        let source_type = input.reader.source_type();
+        let output = output.clone();
+        let selection = selection.clone();
+        let reloads = reloads.clone();
 
         let fut = match source_type {
             SourceType::Stdin => tokio::task::spawn(async move {
-                read_stdin(reader).await;
+                read_stdin(input, output, selection, reloads).await;
             }),
             SourceType::File(file_path) => {
                 let (mut watcher, rx) = new_async_watcher().map_err(|e| anyhow!(e))?;
@@ -66,7 +439,9 @@ async fn main() -> anyhow::Result<()> {
                     .map_err(|e| anyhow!(e))?;
 
                 tokio::task::spawn(async move {
-                    read_file(file_path, follow, reader, rx).await;
+                    // Events stop once the watcher is dropped.
+                    let _watcher = watcher;
+                    read_file(file_path, follow, input, output, selection, reloads, rx).await;
                 })
             }
         };
@@ -75,9 +450,185 @@ async fn main() -> anyhow::Result<()> {
     }
 
     join_all(futs).await;
+    if let Err(e) = output.lock().unwrap().finish() {
+        output_error(e);
+    }
     Ok(())
 }
 
+// The sources in the order they're given. A `--format` applies to the paths after it.
+fn source_specs(
+    reader_builder: &ReaderBuilder,
+    matches: &ArgMatches,
+) -> anyhow::Result<Vec<SourceSpec>> {
+    let indexed = |id: &str| -> Vec<(usize, String)> {
+        matches
+            .indices_of(id)
+            .unwrap_or_default()
+            .zip(matches.get_many::<String>(id).unwrap_or_default().cloned())
+            .collect()
+    };
+    let formats = indexed("format");
+    let paths = indexed("source");
+
+    let mut specs = Vec::new();
+    for (index, path) in &paths {
+        let spec = match formats.iter().rev().find(|(i, _)| i < index) {
+            Some((_, format)) => SourceSpec {
+                path: path.clone(),
+                format: Some(format.clone()),
+                ..Default::default()
+            },
+            None => reader_builder.source_spec(path),
+        };
+        specs.push((*index, spec));
+    }
+    if let Some((index, format)) = formats.last() {
+        if !paths.iter().any(|(i, _)| i > index) {
+            bail!("--format {} isn't followed by any path", format);
+        }
+    }
+    for (index, spec) in indexed("spec") {
+        specs.push((index, SourceSpec::parse(&spec)?));
+    }
+
+    specs.sort_by_key(|(index, _)| *index);
+    Ok(specs.into_iter().map(|(_, spec)| spec).collect())
+}
+
+fn source_args() -> [Arg; 3] {
+    [
+        arg!([source] "Files to operate on. '<format>:<path>' reads a file with a format, e.g. 'nginx:access.log'. 'stdin' is the standard input.").action(ArgAction::Append),
+        arg!(spec: --source <SPEC> "A source with options: 'path=<path>,format=<format>,label=<label>,encoding=<utf8|latin1>'. The label is shown instead of the path. Can be given more than once.").action(ArgAction::Append),
+        arg!(--format <FORMAT> "Read the paths after this with a format, e.g. '--format nginx a.log b.log'. Can be given more than once.").action(ArgAction::Append),
+    ]
+}
+
+fn cli() -> Command {
+    Command::new("falog")
+        .arg(arg!(--config <PATH> "Use this config file instead of /etc/falog/falog.yml, $XDG_CONFIG_HOME/falog/falog.yml and the project's falog.yml. Can also be set with FALOG_CONFIG.").global(true))
+        .arg(arg!(--set <KEY_VALUE> "Set a config key, e.g. 'formats.json.fields.message=text'. Lists are written '[a, b]'. Overrides the config files and FALOG_<KEY> environment variables, where dots are written '__', e.g. FALOG_FORMATS__JSON__FIELDS__MESSAGE. Can be given more than once.").action(ArgAction::Append).global(true))
+        .args(source_args())
+        .arg(arg!(-f --follow "Print logs as they are appended. Works only on files. Usage is redundant with stdin input.").action(ArgAction::SetTrue))
+        .arg(arg!(-o --output <FORMAT> "Output format").value_parser(OUTPUT_FORMATS).default_value("pretty"))
+        .arg(arg!(--columns <COLUMNS> "Show records as an aligned table with these comma separated columns, e.g. 'ts,status,method,path,duration'. Columns take the same options as template fields, e.g. 'ts:%H:%M:%S'. Implies table output.").conflicts_with_all(["output", "template"]))
+        .arg(arg!(--highlight <REGEX> "Highlight matches of a regex in messages. Can be given more than once. If the regex has a capture named 'token', only that part is highlighted.").action(ArgAction::Append))
+        .arg(arg!(--color <WHEN> "When to use colors. 'auto' uses them if stdout is a terminal and NO_COLOR isn't set.").value_parser(["always", "auto", "never"]).default_value("auto"))
+        .arg(arg!(--"time-format" <FORMAT> "Show timestamps with a strftime pattern, e.g. '%H:%M:%S%.3f'"))
+        .arg(arg!(--utc "Show timestamps in UTC").conflicts_with_all(["local", "tz"]))
+        .arg(arg!(--local "Show timestamps in the local time zone").conflicts_with("tz"))
+        .arg(arg!(--tz <ZONE> "Show timestamps in a time zone, e.g. 'Europe/Berlin'"))
+        .arg(arg!(--relative "Show timestamps relative to now, e.g. '3m ago'").conflicts_with("delta"))
+        .arg(arg!(--delta "Show the time since the previous record instead of timestamps"))
+        .arg(arg!(--filter <FILTER> "Only show records matching a filter, e.g. 'level >= warn && path ~ \"^/api\"'"))
+        .arg(arg!(-g --grep <REGEX> "Only show records matching a regex"))
+        .arg(arg!(-A --after <N> "Show N records after each match").value_parser(clap::value_parser!(usize)))
+        .arg(arg!(-B --before <N> "Show N records before each match").value_parser(clap::value_parser!(usize)))
+        .arg(arg!(-C --context <N> "Show N records before and after each match").value_parser(clap::value_parser!(usize)))
+        .arg(arg!(-i --interactive "Browse records in a full-screen pager with search, live filtering and column picking. With -f, sticks to the last record as records are appended.").conflicts_with_all(["output", "template", "grep", "after", "before", "context"]))
+        .arg(arg!(-t --template <TEMPLATE> "Show records with a template, e.g. '{ts:%H:%M:%S} {level:>5} [{source}] {message} {rest}'. Implies pretty output.").conflicts_with("output"))
+        .subcommand(
+            Command::new("stats")
+                .about("Show statistics of the records instead of the records")
+                .args(source_args())
+                .arg(arg!(--by <FIELDS> "Count records by the values of these comma separated fields, e.g. 'method,status'. Can be given more than once for separate counts.").action(ArgAction::Append).default_value("level"))
+                .arg(arg!(--top <N> "Show the N most common values of each count").value_parser(clap::value_parser!(usize)).default_value("10"))
+                .arg(arg!(--field <FIELDS> "Show min, max, average and percentiles of these comma separated numeric fields, e.g. 'duration'").action(ArgAction::Append))
+                .arg(arg!(--interval <DURATION> "Count records per interval, e.g. '1m' or '1h'. Widened past 1000 intervals"))
+                .arg(arg!(--filter <FILTER> "Only count records matching a filter, e.g. 'level >= warn'"))
+                .arg(arg!(-g --grep <REGEX> "Only count records matching a regex"))
+                .arg(arg!(-o --output <FORMAT> "Output format").value_parser(STATS_FORMATS).default_value("table")),
+        )
+        .subcommand(
+            Command::new("histogram")
+                .about("Draw a bar chart of how many records there are over time")
+                .args(source_args())
+                .arg(arg!(--bucket <DURATION> "How much time each bar covers, e.g. '1m' or '1h'. Widened past 1000 bars").default_value("1m"))
+                .arg(arg!(--by <FIELD> "Stack bars by the values of a field, e.g. 'level'"))
+                .arg(arg!(--sparkline "Draw a single line per value of --by instead of a bar per bucket"))
+                .arg(arg!(--color <WHEN> "When to use colors. 'auto' uses them if stdout is a terminal and NO_COLOR isn't set.").value_parser(["always", "auto", "never"]).default_value("auto"))
+                .arg(arg!(--filter <FILTER> "Only count records matching a filter, e.g. 'status >= 500'"))
+                .arg(arg!(-g --grep <REGEX> "Only count records matching a regex")),
+        )
+        .subcommand(
+            Command::new("test-formats")
+                .about("Check that the `examples` of formats are read as expected")
+                .arg(arg!([format] "Formats to test, all of them if not given").action(ArgAction::Append)),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Work with the config files")
+                .subcommand_required(true)
+                .subcommand(Command::new("check").about("Check the config files and print every problem found, with its location")),
+        )
+        .version(crate_version!())
+}
+
+fn context(matches: &ArgMatches) -> (Option<usize>, Option<usize>) {
+    let context = matches.get_one::<usize>("context").copied();
+    let before = matches.get_one::<usize>("before").copied().or(context);
+    let after = matches.get_one::<usize>("after").copied().or(context);
+    (before, after)
+}
+
+// `before` and `after` are how many records of context are shown around matches.
+fn selection(
+    matches: &ArgMatches,
+    before: Option<usize>,
+    after: Option<usize>,
+) -> anyhow::Result<Option<Selection>> {
+    let filter = matches
+        .get_one::<String>("filter")
+        .map(|filter| Filter::parse(filter))
+        .transpose()?;
+    let grep = matches
+        .get_one::<String>("grep")
+        .map(|grep| Regex::new(grep).map_err(|e| anyhow!("--grep: invalid regex: {}", e)))
+        .transpose()?;
+
+    if filter.is_none() && grep.is_none() {
+        if before.is_some() || after.is_some() {
+            bail!("-A, -B and -C need a --filter or --grep to show context around");
+        }
+        return Ok(None);
+    }
+    Ok(Some(Selection {
+        filter,
+        grep,
+        before: before.unwrap_or(0),
+        after: after.unwrap_or(0),
+    }))
+}
+
+fn time_options(matches: &ArgMatches) -> anyhow::Result<TimeOptions> {
+    let format = matches.get_one::<String>("time-format").cloned();
+    if let Some(format) = &format {
+        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+            bail!("--time-format: invalid time format `{}`", format);
+        }
+    }
+
+    let zone = if matches.get_flag("utc") {
+        Zone::Utc
+    } else if matches.get_flag("local") {
+        Zone::Local
+    } else if let Some(tz) = matches.get_one::<String>("tz") {
+        Zone::parse(tz).map_err(|e| anyhow!("--tz: {}", e))?
+    } else {
+        Zone::Original
+    };
+
+    let mode = if matches.get_flag("relative") {
+        Mode::Relative
+    } else if matches.get_flag("delta") {
+        Mode::Delta
+    } else {
+        Mode::Absolute
+    };
+
+    Ok(TimeOptions { format, zone, mode })
+}
+
 fn new_async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
     let (mut tx, rx) = channel(1);
 
@@ -94,63 +645,172 @@ fn new_async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::R
     Ok((watcher, rx))
 }
 
-async fn read_stdin(mut reader: Box<dyn Reader + Send>) {
-    println!("Read stdin");
-    loop {
-        match reader.read_fields().await {
-            Ok(fields) => {
-                println!("{:?}", fields);
-            }
-            Err(e) => {
-                if e == ReadError::Eof {
-                    println!("stdin EOF");
-                    break;
-                }
-                println!("Error: stdin: {e}");
-            }
-        }
+async fn read_stdin(
+    mut input: Input,
+    output: SharedOutput,
+    selection: Option<Arc<Selection>>,
+    mut reloads: Option<Reloads>,
+) {
+    let mut selected = selection.map(Selected::new);
+    while let Stopped::Reloaded(builder) =
+        read_records(&mut input, &output, &mut selected, &mut reloads).await
+    {
+        input = reload_format(input, &builder, &output);
     }
 }
 
 async fn read_file(
     file_path: String,
     follow: bool,
-    mut reader: Box<dyn Reader + Send>,
+    mut input: Input,
+    output: SharedOutput,
+    selection: Option<Arc<Selection>>,
+    mut reloads: Option<Reloads>,
     mut rx: Receiver<notify::Result<Event>>,
 ) {
-    println!("Reading {file_path}");
-
+    let mut selected = selection.map(Selected::new);
     loop {
-        loop {
-            match reader.read_fields().await {
-                Ok(fields) => {
-                    println!("{:?}", fields);
-                }
-                Err(e) => {
-                    if e == ReadError::Eof {
-                        println!("{file_path} EOF");
-                        break;
-                    }
-                    println!("Error: {file_path}: {e}");
-                }
-            }
+        if let Stopped::Reloaded(builder) =
+            read_records(&mut input, &output, &mut selected, &mut reloads).await
+        {
+            input = reload_format(input, &builder, &output);
+            continue;
         }
 
         if !follow {
             return;
         }
 
-        let res = rx.next().await;
-        if let Some(res) = res {
-            match res {
-                Ok(event) => {
-                    println!("changed: {:?}", event);
-                    continue;
+        tokio::select! {
+            res = rx.next() => match res {
+                Some(Ok(_)) => {}
+                Some(Err(e)) => eprintln!("watch error: {file_path} {:?}", e),
+                None => return,
+            },
+            builder = reloaded(&mut reloads) => input = reload_format(input, &builder, &output),
+        }
+    }
+}
+
+// Resolves when the config is reloaded, never if it isn't watched.
+async fn reloaded(reloads: &mut Option<Reloads>) -> Arc<ReaderBuilder> {
+    if let Some(reloads) = reloads {
+        if reloads.changed().await.is_ok() {
+            return reloads.borrow_and_update().clone();
+        }
+    }
+    std::future::pending().await
+}
+
+// Reads the rest of the input with its format from a reloaded config. An input keeps its
+// format even if the reloaded `path_matches` would choose another one.
+fn reload_format(mut input: Input, builder: &ReaderBuilder, output: &SharedOutput) -> Input {
+    match builder.find_format(&input.format_name) {
+        Ok(format) => input.reader = format.resume(input.reader),
+        Err(e) => output.lock().unwrap().reload_error(&anyhow!(
+            "{}: {}, the old format is still used",
+            input.source_name(),
+            e
+        )),
+    }
+    input
+}
+
+// Which records of a source are shown, see `Selection`.
+struct Selected {
+    selection: Arc<Selection>,
+    // Records as they were read and parsed.
+    window: Window<(String, FieldMap)>,
+}
+
+impl Selected {
+    fn new(selection: Arc<Selection>) -> Self {
+        let window = Window::new(selection.before, selection.after);
+        Self { selection, window }
+    }
+}
+
+// Why `read_records` stopped.
+enum Stopped {
+    Eof,
+    // The config was reloaded, the rest of the input is read with its formats.
+    Reloaded(Arc<ReaderBuilder>),
+}
+
+// Reads records until EOF and writes them to `output`.
+async fn read_records(
+    input: &mut Input,
+    output: &SharedOutput,
+    selected: &mut Option<Selected>,
+    reloads: &mut Option<Reloads>,
+) -> Stopped {
+    let source_name = input.source_name();
+    let reader = &mut input.reader;
+    let source_type = reader.source_type();
+    loop {
+        // Between records, so that none is cut in half.
+        if let Some(reloads) = reloads {
+            if reloads.has_changed().unwrap_or(false) {
+                return Stopped::Reloaded(reloads.borrow_and_update().clone());
+            }
+        }
+        match reader.read_fields().await {
+            Ok(fields) => {
+                let raw = reader.take_raw();
+                let emitted = match selected {
+                    Some(selected) => {
+                        let record = Record {
+                            source: &source_type,
+                            label: input.label.as_deref(),
+                            format_name: &input.format_name,
+                            format_fields: reader.format_fields(),
+                            raw: &raw,
+                            fields: &fields,
+                        };
+                        let matched = selected.selection.matches(&record);
+                        selected.window.push((raw, fields), matched)
+                    }
+                    None => vec![Emit::Record((raw, fields))],
+                };
+
+                for emit in emitted {
+                    let result = match emit {
+                        Emit::Record((raw, fields)) => {
+                            let record = Record {
+                                source: &source_type,
+                                label: input.label.as_deref(),
+                                format_name: &input.format_name,
+                                format_fields: reader.format_fields(),
+                                raw: &raw,
+                                fields: &fields,
+                            };
+                            output.lock().unwrap().write(&record)
+                        }
+                        Emit::Separator => output.lock().unwrap().separator(),
+                    };
+                    if let Err(e) = result {
+                        output_error(e);
+                    }
                 }
-                Err(e) => println!("watch error: {file_path} {:?}", e),
             }
-        } else {
-            println!("{file_path} res is None");
+            Err(e) => {
+                if e == ReadError::Eof {
+                    if let Err(e) = output.lock().unwrap().flush() {
+                        output_error(e);
+                    }
+                    return Stopped::Eof;
+                }
+                reader.take_raw();
+                output.lock().unwrap().read_error(&source_name, &e);
+            }
         }
     }
 }
+
+fn output_error(e: io::Error) {
+    // e.g. `falog access.log | head`
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        std::process::exit(0);
+    }
+    eprintln!("Error: output: {e}");
+}
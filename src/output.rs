@@ -0,0 +1,228 @@
+use crate::{
+    config::{Fields, Stacktrace},
+    field::{Field, FieldMap},
+    highlight::Highlighters,
+    level::Level,
+    output_csv::CsvOutput,
+    output_json::JsonOutput,
+    output_logfmt::LogfmtOutput,
+    output_pretty::PrettyOutput,
+    output_raw::RawOutput,
+    output_table::{Column, TableOutput},
+    reader::ReadError,
+    source::SourceType,
+    template::Template,
+    theme::Theme,
+    timestamp::{self, TimeOptions},
+};
+use chrono::{DateTime, FixedOffset};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+pub const OUTPUT_FORMATS: [&str; 7] = ["pretty", "table", "json", "logfmt", "csv", "tsv", "raw"];
+
+// A parsed record along with what an output needs to know about where it came from.
+pub struct Record<'a> {
+    pub source: &'a SourceType,
+    // Shown instead of the source, see `--source`.
+    pub label: Option<&'a str>,
+    pub format_name: &'a str,
+    // Which fields hold the message, level, timestamp and stacktrace.
+    pub format_fields: &'a Fields,
+    pub raw: &'a str,
+    pub fields: &'a FieldMap,
+}
+
+impl Record<'_> {
+    // What the source is shown as.
+    pub fn source_name(&self) -> String {
+        match self.label {
+            Some(label) => label.to_string(),
+            None => self.source.to_string(),
+        }
+    }
+
+    pub fn level(&self) -> Option<Level> {
+        let name = self.format_fields.level.as_deref().unwrap_or("level");
+        Level::parse(&self.fields.get(name)?.value)
+    }
+
+    // The parsed timestamp, if the format sets which field holds it.
+    pub fn timestamp(&self) -> Option<DateTime<FixedOffset>> {
+        let timestamp = self.format_fields.timestamp.as_ref()?;
+        timestamp::parse(&self.fields.get(&timestamp.name)?.value, &timestamp.format)
+    }
+
+    // The name of a field, with the `message`, `level`, `timestamp` (or `ts`) and
+    // `stacktrace` roles resolved to the fields the format sets for them.
+    pub fn field_name<'b>(&'b self, field: &'b str) -> &'b str {
+        let fields = self.format_fields;
+        match field {
+            "message" => fields.message.as_deref().unwrap_or(field),
+            "level" => fields.level.as_deref().unwrap_or(field),
+            "timestamp" | "ts" => match &fields.timestamp {
+                Some(timestamp) => &timestamp.name,
+                None => field,
+            },
+            "stacktrace" => fields.stacktrace.as_deref().unwrap_or(field),
+            _ => field,
+        }
+    }
+
+    pub fn value(&self, field: &str) -> Option<&str> {
+        let name = self.field_name(field);
+        self.fields.get(name).map(|field| field.value.as_str())
+    }
+
+    pub fn stacktrace(&self) -> Option<&Field> {
+        let name = self
+            .format_fields
+            .stacktrace
+            .as_deref()
+            .unwrap_or("stacktrace");
+        self.fields
+            .get(name)
+            .filter(|field| !field.value.is_empty())
+    }
+}
+
+// Owns what a `Record` borrows, for tests. Fields are text, the format is json and the
+// source is stdin unless changed.
+#[cfg(test)]
+pub struct TestRecord {
+    pub source: SourceType,
+    pub label: Option<String>,
+    pub format_fields: Fields,
+    pub raw: String,
+    pub fields: FieldMap,
+}
+
+#[cfg(test)]
+impl TestRecord {
+    pub fn new(fields: &[(&str, &str)]) -> Self {
+        let fields = fields
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    Field::new(name.to_string(), value.to_string()),
+                )
+            })
+            .collect();
+        Self {
+            source: SourceType::Stdin,
+            label: None,
+            format_fields: Fields::default(),
+            raw: String::new(),
+            fields,
+        }
+    }
+
+    pub fn format_fields(mut self, format_fields: Fields) -> Self {
+        self.format_fields = format_fields;
+        self
+    }
+
+    pub fn timestamp(mut self, name: &str, format: &str) -> Self {
+        self.format_fields.timestamp = Some(crate::config::TimestampField {
+            name: name.to_string(),
+            format: format.to_string(),
+        });
+        self
+    }
+
+    pub fn raw(mut self, raw: String) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    pub fn record(&self) -> Record<'_> {
+        Record {
+            source: &self.source,
+            label: self.label.as_deref(),
+            format_name: "json",
+            format_fields: &self.format_fields,
+            raw: &self.raw,
+            fields: &self.fields,
+        }
+    }
+}
+
+pub trait Output {
+    fn write(&mut self, record: &Record) -> io::Result<()>;
+
+    // Called when a source has no more records for now, e.g. at EOF or while waiting for a
+    // followed file to grow. Outputs that hold records back should write them out.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Written between groups of records that aren't next to each other, e.g. with `-C`.
+    fn separator(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Called when a record can't be read. Outputs that take over the terminal show the error
+    // themselves.
+    fn read_error(&mut self, source: &str, error: &ReadError) {
+        eprintln!("Error: {source}: {error}");
+    }
+
+    // Called when the config is reloaded while following. Outputs that render records with
+    // the config's templates, highlight rules or theme switch to the new ones.
+    fn reload(&mut self, _options: ConfigOptions) {}
+
+    // Called when the config can't be reloaded. The old one stays in use.
+    fn reload_error(&mut self, error: &anyhow::Error) {
+        eprintln!("Error: config reload: {error}");
+    }
+
+    // Called once after every source is read to the end. Outputs that summarize the
+    // records, like `falog stats`, write their summary here.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Readers run concurrently, all writing to the same output.
+pub type SharedOutput = Arc<Mutex<Box<dyn Output + Send>>>;
+
+pub struct OutputOptions {
+    // Whether it's worth showing which source a record came from.
+    pub multiple_sources: bool,
+    pub color: bool,
+    pub theme: Theme,
+    // Used for every record instead of the format templates.
+    pub template: Option<Template>,
+    // Templates of formats, by format name.
+    pub format_templates: HashMap<String, Template>,
+    // Columns of the `table` output.
+    pub columns: Vec<Column>,
+    pub stacktrace: Stacktrace,
+    pub highlighters: Highlighters,
+    pub time: TimeOptions,
+}
+
+// The options of `OutputOptions` that come from the config.
+pub struct ConfigOptions {
+    pub theme: Theme,
+    pub format_templates: HashMap<String, Template>,
+    pub stacktrace: Stacktrace,
+    pub highlighters: Highlighters,
+}
+
+pub fn new_output(format: &str, options: OutputOptions) -> Box<dyn Output + Send> {
+    let stdout = Box::new(io::stdout());
+    match format {
+        "json" => Box::new(JsonOutput::new(stdout)),
+        "logfmt" => Box::new(LogfmtOutput::new(stdout)),
+        "csv" => Box::new(CsvOutput::new(stdout, b',')),
+        "tsv" => Box::new(CsvOutput::new(stdout, b'\t')),
+        "raw" => Box::new(RawOutput::new(stdout)),
+        "table" => Box::new(TableOutput::new(stdout, options)),
+        _ => Box::new(PrettyOutput::new(stdout, options)),
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{
+    output::{Output, Record},
+    output_table::SAMPLE_SIZE,
+};
+use indexmap::IndexSet;
+use std::io::{self, Write};
+
+// Writes delimited values with a header row. The columns are the fields of the first
+// records, in the order they're first seen, so nothing is written until enough records are
+// sampled or the input pauses. Fields that a record doesn't have are left empty, fields
+// first seen after the sample are dropped.
+pub struct CsvOutput {
+    writer: csv::Writer<Box<dyn Write + Send>>,
+    sample: Vec<Vec<(String, String)>>,
+    // Set once the sample is written.
+    columns: Option<Vec<String>>,
+}
+
+impl CsvOutput {
+    pub fn new(writer: Box<dyn Write + Send>, delimiter: u8) -> Self {
+        let writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(writer);
+        Self {
+            writer,
+            sample: Vec::new(),
+            columns: None,
+        }
+    }
+}
+
+impl Output for CsvOutput {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let Some(columns) = &self.columns else {
+            let row = record
+                .fields
+                .iter()
+                .map(|(name, field)| (name.clone(), field.value.clone()))
+                .collect();
+            self.sample.push(row);
+            if self.sample.len() >= SAMPLE_SIZE {
+                self.flush()?;
+            }
+            return Ok(());
+        };
+
+        write_row(&mut self.writer, columns, |column| {
+            record.fields.get(column).map(|field| field.value.as_str())
+        })?;
+        self.writer.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.columns.is_none() && !self.sample.is_empty() {
+            let sample = std::mem::take(&mut self.sample);
+            let columns: IndexSet<&String> = sample
+                .iter()
+                .flat_map(|row| row.iter().map(|(name, _)| name))
+                .collect();
+            let columns: Vec<String> = columns.into_iter().cloned().collect();
+            self.writer.write_record(&columns)?;
+            for row in &sample {
+                write_row(&mut self.writer, &columns, |column| {
+                    row.iter()
+                        .find(|(name, _)| name == column)
+                        .map(|(_, value)| value.as_str())
+                })?;
+            }
+            self.columns = Some(columns);
+        }
+        self.writer.flush()
+    }
+}
+
+fn write_row<'a, W: Write>(
+    writer: &mut csv::Writer<W>,
+    columns: &[String],
+    value: impl Fn(&str) -> Option<&'a str>,
+) -> io::Result<()> {
+    let row = columns.iter().map(|column| value(column).unwrap_or(""));
+    writer.write_record(row)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsvOutput;
+    use crate::output::{Output, TestRecord};
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn csv_columns() {
+        let buffer = Buffer::default();
+        let mut csv = CsvOutput::new(Box::new(buffer.clone()), b',');
+        for fields in [
+            &[("level", "info"), ("msg", "started")][..],
+            &[
+                ("level", "error"),
+                ("msg", "failed"),
+                ("error", "timed out"),
+            ],
+            &[("msg", "a, b")],
+        ] {
+            csv.write(&TestRecord::new(fields).record()).unwrap();
+        }
+        assert!(buffer.0.lock().unwrap().is_empty());
+        csv.flush().unwrap();
+        // Columns are fixed once the sample is written.
+        let record = TestRecord::new(&[("level", "warn"), ("user", "bob")]);
+        csv.write(&record.record()).unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            "level,msg,error\n\
+             info,started,\n\
+             error,failed,timed out\n\
+             ,\"a, b\",\n\
+             warn,,\n",
+            output
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use crate::{
+    field::{Field, Kind},
+    output::{Output, Record},
+};
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+
+// Writes one JSON object per record. Numbers, booleans, nulls and nested values
+// read from JSON input keep their type, everything else is a string.
+pub struct JsonOutput {
+    writer: Box<dyn Write + Send>,
+}
+
+impl JsonOutput {
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self { writer }
+    }
+}
+
+pub fn to_json_value(field: &Field) -> Value {
+    match field.kind {
+        Kind::String => Value::String(field.value.clone()),
+        _ => serde_json::from_str(&field.value)
+            .unwrap_or_else(|_| Value::String(field.value.clone())),
+    }
+}
+
+impl Output for JsonOutput {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let object: Map<String, Value> = record
+            .fields
+            .iter()
+            .map(|(name, field)| (name.clone(), to_json_value(field)))
+            .collect();
+        serde_json::to_writer(&mut self.writer, &object)?;
+        self.writer.write_all(b"\n")
+    }
+}
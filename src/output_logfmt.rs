@@ -0,0 +1,63 @@
+use crate::output::{Output, Record};
+use std::io::{self, Write};
+
+pub struct LogfmtOutput {
+    writer: Box<dyn Write + Send>,
+}
+
+impl LogfmtOutput {
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self { writer }
+    }
+}
+
+// Keys can't contain spaces, `=` or `"`, so those are replaced with `_`.
+pub fn escape_key(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_whitespace() || c.is_control() || c == '=' || c == '"' {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+// Values are quoted when they're empty or contain spaces, `=`, `"` or control characters.
+pub fn escape_value(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '=' || c == '"');
+    if !needs_quotes {
+        return value.to_string();
+    }
+
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+impl Output for LogfmtOutput {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let line = record
+            .fields
+            .iter()
+            .map(|(name, field)| format!("{}={}", escape_key(name), escape_value(&field.value)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(self.writer, "{}", line)
+    }
+}
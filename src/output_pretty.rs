@@ -0,0 +1,152 @@
+use crate::{
+    level::Level,
+    output::{ConfigOptions, Output, OutputOptions, Record},
+    output_logfmt::{escape_key, escape_value},
+    stacktrace,
+    template::RenderContext,
+    theme::Theme,
+    timestamp::TimeDisplay,
+};
+use chrono::{DateTime, FixedOffset};
+use std::io::{self, Write};
+
+// Human readable output. Records are rendered with the template given on the command line,
+// or the template of their format, or else as
+// `<timestamp> <LEVEL> [<source>] <message> key=value ...`
+// Stack traces are shown on indented lines below the record.
+pub struct PrettyOutput {
+    writer: Box<dyn Write + Send>,
+    options: OutputOptions,
+    // The timestamp of the previous record, for `--delta`.
+    previous: Option<DateTime<FixedOffset>>,
+}
+
+impl PrettyOutput {
+    pub fn new(writer: Box<dyn Write + Send>, options: OutputOptions) -> Self {
+        Self {
+            writer,
+            options,
+            previous: None,
+        }
+    }
+
+    // Set if colors are used.
+    fn theme(&self) -> Option<&Theme> {
+        self.options.color.then_some(&self.options.theme)
+    }
+
+    fn render(&self, record: &Record) -> String {
+        let paint = |key: &str, s: &str| match self.theme() {
+            Some(theme) => theme.paint(key, s),
+            None => s.to_string(),
+        };
+        let format_fields = record.format_fields;
+        let timestamp_name = format_fields.timestamp.as_ref().map(|t| t.name.as_str());
+        let level_name = format_fields.level.as_deref();
+        let message_name = format_fields.message.as_deref();
+        let stacktrace_name = Some(format_fields.stacktrace.as_deref().unwrap_or("stacktrace"));
+
+        let get = |name: Option<&str>| name.and_then(|name| record.fields.get(name));
+
+        let mut parts = Vec::new();
+        if let Some(timestamp) = get(timestamp_name) {
+            let shown = record.timestamp().and_then(|time| {
+                TimeDisplay::new(&self.options.time, time, self.previous).format(None)
+            });
+            parts.push(paint(
+                "timestamp",
+                shown.as_ref().unwrap_or(&timestamp.value),
+            ));
+        }
+        if let Some(level) = get(level_name) {
+            let parsed = Level::parse(&level.value);
+            let name = match parsed {
+                Some(level) => level.as_str().to_string(),
+                None => level.value.to_uppercase(),
+            };
+            let name = format!("{:<5}", name);
+            match (parsed, self.theme()) {
+                (Some(level), Some(theme)) => parts.push(theme.level(level).paint(&name)),
+                _ => parts.push(name),
+            }
+        }
+        if self.options.multiple_sources {
+            parts.push(paint("source", &format!("[{}]", record.source_name())));
+        }
+        if let Some(message) = get(message_name) {
+            match self.theme() {
+                Some(theme) => {
+                    let highlighter = self.options.highlighters.get(record.format_name);
+                    parts.push(highlighter.highlight(&message.value, theme));
+                }
+                None => parts.push(message.value.clone()),
+            }
+        }
+
+        for (name, field) in record.fields {
+            let name = Some(name.as_str());
+            if name == timestamp_name
+                || name == level_name
+                || name == message_name
+                || name == stacktrace_name
+            {
+                continue;
+            }
+            parts.push(format!(
+                "{}={}",
+                paint("key", &escape_key(&field.name)),
+                paint("value", &escape_value(&field.value))
+            ));
+        }
+
+        parts.join(" ")
+    }
+}
+
+impl Output for PrettyOutput {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let template = self
+            .options
+            .template
+            .as_ref()
+            .or_else(|| self.options.format_templates.get(record.format_name));
+        let (line, shows_stacktrace) = match template {
+            Some(template) => (
+                template.render(
+                    record,
+                    &RenderContext {
+                        theme: self.theme(),
+                        highlighter: Some(self.options.highlighters.get(record.format_name)),
+                        time: Some(&self.options.time),
+                        previous: self.previous,
+                    },
+                ),
+                template.shows_stacktrace(),
+            ),
+            None => (self.render(record), false),
+        };
+        writeln!(self.writer, "{}", line)?;
+        if let Some(time) = record.timestamp() {
+            self.previous = Some(time);
+        }
+
+        if let Some(field) = record.stacktrace().filter(|_| !shows_stacktrace) {
+            let trace = stacktrace::text(field);
+            for line in stacktrace::render(&trace, &self.options.stacktrace, self.theme()) {
+                writeln!(self.writer, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn separator(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "--")
+    }
+
+    fn reload(&mut self, options: ConfigOptions) {
+        self.options.theme = options.theme;
+        self.options.format_templates = options.format_templates;
+        self.options.stacktrace = options.stacktrace;
+        self.options.highlighters = options.highlighters;
+    }
+}
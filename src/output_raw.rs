@@ -0,0 +1,23 @@
+use crate::output::{Output, Record};
+use std::io::{self, Write};
+
+// Writes records as they were read.
+pub struct RawOutput {
+    writer: Box<dyn Write + Send>,
+}
+
+impl RawOutput {
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self { writer }
+    }
+}
+
+impl Output for RawOutput {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        writeln!(self.writer, "{}", record.raw.trim_end_matches(['\n', '\r']))
+    }
+
+    fn separator(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "--")
+    }
+}
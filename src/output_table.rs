@@ -0,0 +1,308 @@
+use crate::{
+    level::Level,
+    output::{ConfigOptions, Output, OutputOptions, Record},
+    style::Style,
+    template::{truncate, RenderContext, Template},
+    theme::Theme,
+    timestamp::TimeOptions,
+};
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, FixedOffset};
+use std::io::{self, Write};
+
+pub const DEFAULT_COLUMNS: &str = "timestamp,level,message";
+
+// Column widths are computed from this many records before anything is written. The
+// `csv` and `tsv` outputs take their columns from as many.
+pub const SAMPLE_SIZE: usize = 100;
+// No column but the last one gets wider than this, longer values are truncated.
+pub const MAX_COLUMN_WIDTH: usize = 40;
+// How often the header is repeated when the terminal height can't be known.
+const HEADER_INTERVAL: usize = 50;
+const SEPARATOR: &str = "  ";
+
+// A column of the `table` output. Columns are written like template fields without the
+// braces, e.g. `ts:%H:%M:%S`, `status`, `user|-` or `source`.
+pub struct Column {
+    name: String,
+    template: Template,
+}
+
+impl Column {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn template(&self) -> &Template {
+        &self.template
+    }
+}
+
+// Parses comma separated columns, e.g. `ts,status,method,path,duration`.
+pub fn parse_columns(columns: &str) -> anyhow::Result<Vec<Column>> {
+    let mut parsed = Vec::new();
+    for column in columns.split(',').map(str::trim) {
+        if column.is_empty() {
+            bail!("empty column in `{}`", columns);
+        }
+        let name = column
+            .split([':', '|'])
+            .next()
+            .unwrap_or(column)
+            .trim()
+            .to_string();
+        let template = Template::parse(&format!("{{{}}}", column))
+            .map_err(|e| anyhow!("column `{}`: {}", column, e))?;
+        parsed.push(Column { name, template });
+    }
+    Ok(parsed)
+}
+
+struct Row {
+    cells: Vec<String>,
+    level: Option<Level>,
+}
+
+// Aligned columns, one record per line.
+pub struct TableOutput {
+    writer: Box<dyn Write + Send>,
+    columns: Vec<Column>,
+    // Set if colors are used.
+    theme: Option<Theme>,
+    // Terminal width and height, if stdout is a terminal.
+    terminal: Option<(usize, usize)>,
+    // Records held back until the column widths are known. None is a separator.
+    sample: Vec<Option<Row>>,
+    widths: Option<Vec<usize>>,
+    lines_since_header: usize,
+    time: TimeOptions,
+    // The timestamp of the previous record, for `--delta`.
+    previous: Option<DateTime<FixedOffset>>,
+}
+
+impl TableOutput {
+    pub fn new(writer: Box<dyn Write + Send>, options: OutputOptions) -> Self {
+        let terminal = terminal_size::terminal_size().map(|(width, height)| {
+            let (terminal_size::Width(width), terminal_size::Height(height)) = (width, height);
+            (width as usize, height as usize)
+        });
+        Self {
+            writer,
+            columns: options.columns,
+            theme: options.color.then_some(options.theme),
+            terminal,
+            sample: Vec::new(),
+            widths: None,
+            lines_since_header: 0,
+            time: options.time,
+            previous: None,
+        }
+    }
+
+    fn row(&mut self, record: &Record) -> Row {
+        let context = RenderContext {
+            time: Some(&self.time),
+            previous: self.previous,
+            ..Default::default()
+        };
+        let cells = self
+            .columns
+            .iter()
+            .map(|column| {
+                column
+                    .template
+                    .render(record, &context)
+                    .replace(['\n', '\r', '\t'], " ")
+            })
+            .collect();
+        if let Some(time) = record.timestamp() {
+            self.previous = Some(time);
+        }
+        Row {
+            cells,
+            level: record.level(),
+        }
+    }
+
+    fn compute_widths(&mut self) {
+        let last = self.columns.len() - 1;
+        let widths = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let width = self
+                    .sample
+                    .iter()
+                    .flatten()
+                    .map(|row| row.cells[i].chars().count())
+                    .chain([column.name.chars().count()])
+                    .max()
+                    .unwrap_or(0);
+                if i == last {
+                    width
+                } else {
+                    width.min(MAX_COLUMN_WIDTH)
+                }
+            })
+            .collect();
+        self.widths = Some(widths);
+    }
+
+    fn header_interval(&self) -> usize {
+        match self.terminal {
+            // Keep a header on screen, leaving room for the header line itself.
+            Some((_, height)) if height > 2 => height - 1,
+            _ => HEADER_INTERVAL,
+        }
+    }
+
+    fn write_row(&mut self, row: &Row) -> io::Result<()> {
+        if self.lines_since_header == 0 {
+            let header = Row {
+                cells: self
+                    .columns
+                    .iter()
+                    .map(|column| column.name.to_uppercase())
+                    .collect(),
+                level: None,
+            };
+            self.write_line(&header, Some("header"))?;
+        }
+        self.write_line(row, None)?;
+
+        self.lines_since_header += 1;
+        if self.lines_since_header >= self.header_interval() {
+            self.lines_since_header = 0;
+        }
+        Ok(())
+    }
+
+    // `line_style` is the theme key to paint the whole line with.
+    fn write_line(&mut self, row: &Row, line_style: Option<&str>) -> io::Result<()> {
+        let widths = self.widths.as_deref().unwrap_or_default();
+        let last = row.cells.len() - 1;
+
+        // Each part is a piece of text and the style to paint it with.
+        let mut parts = Vec::with_capacity(row.cells.len() * 2);
+        for (i, cell) in row.cells.iter().enumerate() {
+            if i > 0 {
+                parts.push((SEPARATOR.to_string(), None));
+            }
+            let text = if i == last {
+                cell.clone()
+            } else {
+                format!("{:<1$}", truncate(cell, widths[i]), widths[i])
+            };
+            let style = match (&self.theme, line_style, row.level) {
+                (Some(theme), Some(key), _) => Some(theme.get(key).clone()),
+                (Some(theme), None, Some(level)) if self.columns[i].name == "level" => {
+                    Some(theme.level(level).clone())
+                }
+                _ => None,
+            };
+            parts.push((text, style));
+        }
+
+        if let Some((width, _)) = self.terminal {
+            fit(&mut parts, width);
+        }
+
+        let line: String = parts
+            .into_iter()
+            .map(|(text, style)| match style {
+                Some(style) => style.paint(&text),
+                None => text,
+            })
+            .collect();
+        writeln!(self.writer, "{}", line.trim_end())
+    }
+}
+
+// Cuts `parts` so that they fit in `width` characters, ending with `…` if anything was cut.
+fn fit(parts: &mut Vec<(String, Option<Style>)>, width: usize) {
+    let total: usize = parts.iter().map(|(text, _)| text.chars().count()).sum();
+    if total <= width {
+        return;
+    }
+
+    let mut budget = width;
+    for i in 0..parts.len() {
+        let len = parts[i].0.chars().count();
+        if len < budget {
+            budget -= len;
+            continue;
+        }
+        let text = &mut parts[i].0;
+        *text = text.chars().take(budget.saturating_sub(1)).collect();
+        if budget > 0 {
+            text.push('…');
+        }
+        parts.truncate(i + 1);
+        return;
+    }
+}
+
+impl Output for TableOutput {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let row = self.row(record);
+        if self.widths.is_some() {
+            return self.write_row(&row);
+        }
+
+        self.sample.push(Some(row));
+        if self.sample.len() >= SAMPLE_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.widths.is_none() && !self.sample.is_empty() {
+            self.compute_widths();
+            for row in std::mem::take(&mut self.sample) {
+                match row {
+                    Some(row) => self.write_row(&row)?,
+                    None => writeln!(self.writer, "--")?,
+                }
+            }
+        }
+        self.writer.flush()
+    }
+
+    fn separator(&mut self) -> io::Result<()> {
+        if self.widths.is_none() {
+            self.sample.push(None);
+            return Ok(());
+        }
+        writeln!(self.writer, "--")
+    }
+
+    fn reload(&mut self, options: ConfigOptions) {
+        if self.theme.is_some() {
+            self.theme = Some(options.theme);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fit, parse_columns};
+
+    #[test]
+    fn table_columns() {
+        let columns = parse_columns("ts:%H:%M:%S, status,user|-").unwrap();
+        let names: Vec<_> = columns.iter().map(|column| column.name.as_str()).collect();
+        assert_eq!(vec!["ts", "status", "user"], names);
+        assert!(parse_columns("ts,,status").is_err());
+
+        let mut parts = vec![
+            ("12:00:00".to_string(), None),
+            ("  ".to_string(), None),
+            ("GET /index.html".to_string(), None),
+        ];
+        fit(&mut parts, 16);
+        let line: String = parts.into_iter().map(|(text, _)| text).collect();
+        assert_eq!("12:00:00  GET /…", line);
+    }
+}
@@ -0,0 +1,1170 @@
+use crate::{
+    config::{Fields, Stacktrace},
+    field::{Field, FieldMap, Kind},
+    filter::{self, Filter},
+    output::{Output, Record},
+    output_table::{self, Column, MAX_COLUMN_WIDTH},
+    reader::ReadError,
+    source::SourceType,
+    stacktrace,
+    style::{self, Color},
+    template::{truncate, RenderContext},
+    theme::Theme,
+    timestamp::{self, TimeOptions},
+};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use indexmap::IndexSet;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color as TermColor, Modifier, Style as TermStyle},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+    DefaultTerminal, Frame,
+};
+use regex::Regex;
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+// How often the screen is redrawn to show new records while no key is pressed.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+// Columns that aren't fields, offered first by the column picker.
+const ROLE_COLUMNS: [&str; 4] = ["timestamp", "level", "source", "message"];
+const HELP: &str = "q quit  / search  f filter  c columns  ⏎ details  t time  F follow";
+
+// A record as the pager keeps it.
+struct Entry {
+    source: SourceType,
+    label: Option<String>,
+    // Index of the format in `Store::formats`.
+    format: usize,
+    raw: String,
+    fields: FieldMap,
+}
+
+// Every record read so far. Readers keep adding to it while the pager shows it.
+#[derive(Default)]
+pub struct Store {
+    // Names and fields of the formats the records were read with.
+    formats: Vec<(String, Fields)>,
+    entries: Vec<Entry>,
+    // Lines that couldn't be read, and the last error.
+    errors: usize,
+    error: Option<String>,
+}
+
+impl Store {
+    fn push(&mut self, record: &Record) {
+        let format = match self
+            .formats
+            .iter()
+            .position(|(name, _)| name == record.format_name)
+        {
+            Some(format) => format,
+            None => {
+                self.formats
+                    .push((record.format_name.to_string(), record.format_fields.clone()));
+                self.formats.len() - 1
+            }
+        };
+        self.entries.push(Entry {
+            source: record.source.clone(),
+            label: record.label.map(String::from),
+            format,
+            raw: record.raw.to_string(),
+            fields: record.fields.clone(),
+        });
+    }
+
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    fn record(&self, index: usize) -> Record<'_> {
+        let entry = &self.entries[index];
+        let (format_name, format_fields) = &self.formats[entry.format];
+        Record {
+            source: &entry.source,
+            label: entry.label.as_deref(),
+            format_name,
+            format_fields,
+            raw: &entry.raw,
+            fields: &entry.fields,
+        }
+    }
+}
+
+pub type SharedStore = Arc<Mutex<Store>>;
+
+// Keeps records for the pager instead of writing them.
+pub struct PagerOutput {
+    store: SharedStore,
+}
+
+impl PagerOutput {
+    pub fn new(store: SharedStore) -> Self {
+        Self { store }
+    }
+}
+
+impl Output for PagerOutput {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        self.store.lock().unwrap().push(record);
+        Ok(())
+    }
+
+    fn read_error(&mut self, source: &str, error: &ReadError) {
+        let mut store = self.store.lock().unwrap();
+        store.errors += 1;
+        store.set_error(format!("{source}: {error}"));
+    }
+
+    fn reload_error(&mut self, error: &anyhow::Error) {
+        self.store
+            .lock()
+            .unwrap()
+            .set_error(format!("config reload: {error}"));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Prompt {
+    Search { backward: bool },
+    Filter,
+    Time,
+}
+
+// A line being edited in the status line.
+struct Input {
+    prompt: Prompt,
+    text: String,
+    // The record selected when the prompt was opened, selected again if it's cancelled.
+    origin: Option<usize>,
+}
+
+enum View {
+    List,
+    // Picking columns, with the cursor on this one.
+    Columns(usize),
+}
+
+// The side panel with every field of the selected record.
+struct Detail {
+    // Index of the field the cursor is on.
+    field: usize,
+    // Whether keys move the cursor in the panel rather than in the list.
+    focused: bool,
+}
+
+// What the pager needs besides the records.
+pub struct PagerOptions {
+    pub columns: Vec<Column>,
+    pub filter: Option<String>,
+    pub follow: bool,
+    pub theme: Theme,
+    pub time: TimeOptions,
+    pub stacktrace: Stacktrace,
+}
+
+// A full-screen view of the records, like `less` for logs.
+pub struct Pager {
+    store: SharedStore,
+    // Indexes of the records that pass the filter, in the order they were read.
+    visible: Vec<usize>,
+    // How many records of the store were checked against the filter.
+    scanned: usize,
+    filter: Option<Filter>,
+    filter_text: String,
+    // Index of the selected record in `visible`.
+    selected: usize,
+    // Index in `visible` of the record at the top of the screen.
+    offset: usize,
+    // Records shown on screen at once.
+    height: usize,
+    // Whether the last record stays selected as records come in.
+    follow: bool,
+    columns: Vec<Column>,
+    // Names of all fields seen, in the order they were first seen.
+    known_fields: IndexSet<String>,
+    search: Option<Regex>,
+    search_backward: bool,
+    input: Option<Input>,
+    view: View,
+    detail: Option<Detail>,
+    // Shown in the status line until the next key is pressed.
+    message: Option<String>,
+    theme: Theme,
+    time: TimeOptions,
+    stacktrace: Stacktrace,
+}
+
+impl Pager {
+    pub fn new(store: SharedStore, options: PagerOptions) -> anyhow::Result<Self> {
+        let filter = options.filter.as_deref().map(Filter::parse).transpose()?;
+        Ok(Self {
+            store,
+            visible: Vec::new(),
+            scanned: 0,
+            filter,
+            filter_text: options.filter.unwrap_or_default(),
+            selected: 0,
+            offset: 0,
+            height: 1,
+            follow: options.follow,
+            columns: options.columns,
+            known_fields: IndexSet::new(),
+            search: None,
+            search_backward: false,
+            input: None,
+            view: View::List,
+            detail: None,
+            message: None,
+            theme: options.theme,
+            time: options.time,
+            stacktrace: options.stacktrace,
+        })
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    // Takes the records read since the last call into account.
+    fn refresh(&mut self) {
+        {
+            let store = self.store.lock().unwrap();
+            for index in self.scanned..store.entries.len() {
+                let record = store.record(index);
+                for name in record.fields.keys() {
+                    if !self.known_fields.contains(name) {
+                        self.known_fields.insert(name.clone());
+                    }
+                }
+                if self
+                    .filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.matches(&record))
+                {
+                    self.visible.push(index);
+                }
+            }
+            self.scanned = store.entries.len();
+        }
+        if self.follow {
+            self.selected = self.visible.len().saturating_sub(1);
+        }
+    }
+
+    // Index in the store of the selected record.
+    fn selected_index(&self) -> Option<usize> {
+        self.visible.get(self.selected).copied()
+    }
+
+    // Selects the record at `index` in the store, or the next one that's visible.
+    fn select_index(&mut self, index: usize) {
+        self.selected = self
+            .visible
+            .partition_point(|visible| *visible < index)
+            .min(self.visible.len().saturating_sub(1));
+    }
+
+    // `anchor` is the record to keep selected, or the next one that passes the new filter.
+    fn set_filter(&mut self, filter: Option<Filter>, anchor: Option<usize>) {
+        self.filter = filter;
+        self.visible.clear();
+        self.scanned = 0;
+        self.refresh();
+        if let (false, Some(anchor)) = (self.follow, anchor) {
+            self.select_index(anchor);
+        }
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        self.follow = false;
+        let last = self.visible.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    fn scroll_into_view(&mut self) {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + self.height {
+            self.offset = self.selected + 1 - self.height;
+        }
+        // Don't leave empty lines at the bottom when the records would fill them.
+        let bottom = self.visible.len().saturating_sub(self.height);
+        self.offset = self.offset.min(bottom);
+    }
+
+    // The next record matching `regex` after the selected one, wrapping around.
+    fn find(&self, regex: &Regex, backward: bool) -> Option<usize> {
+        let len = self.visible.len();
+        if len == 0 {
+            return None;
+        }
+        let from = if backward {
+            (self.selected + len - 1) % len
+        } else {
+            (self.selected + 1) % len
+        };
+        let store = self.store.lock().unwrap();
+        (0..len)
+            .map(|i| {
+                if backward {
+                    (from + len - i % len) % len
+                } else {
+                    (from + i) % len
+                }
+            })
+            .find(|i| regex.is_match(&store.entries[self.visible[*i]].raw))
+    }
+
+    fn search_next(&mut self, reverse: bool) {
+        let Some(regex) = &self.search else {
+            return;
+        };
+        let backward = self.search_backward != reverse;
+        match self.find(regex, backward) {
+            Some(found) => {
+                self.follow = false;
+                self.selected = found;
+            }
+            None => self.message = Some("Pattern not found".to_string()),
+        }
+    }
+
+    // Selects the first record at or after `text`, e.g. `2024-01-01 10:00` or `10:00`.
+    fn jump_to_time(&mut self, text: &str) {
+        let store = self.store.lock().unwrap();
+        let reference = self
+            .visible
+            .get(self.selected)
+            .and_then(|index| store.record(*index).timestamp());
+        let Some(target) = parse_time(text, reference) else {
+            self.message = Some(format!("Invalid time `{}`", text));
+            return;
+        };
+        let found = self.visible.iter().position(|index| {
+            store
+                .record(*index)
+                .timestamp()
+                .is_some_and(|time| time >= target)
+        });
+        match found {
+            Some(found) => {
+                self.follow = false;
+                self.selected = found;
+            }
+            None => self.message = Some(format!("No record at or after {}", target.to_rfc3339())),
+        }
+    }
+
+    // Candidates of the column picker: the roles, then every field seen.
+    fn column_candidates(&self) -> Vec<String> {
+        ROLE_COLUMNS
+            .iter()
+            .map(|role| role.to_string())
+            .chain(
+                self.known_fields
+                    .iter()
+                    .filter(|name| !ROLE_COLUMNS.contains(&name.as_str()))
+                    .cloned(),
+            )
+            .collect()
+    }
+
+    fn toggle_column(&mut self, name: &str) {
+        if let Some(i) = self.columns.iter().position(|column| column.name() == name) {
+            if self.columns.len() > 1 {
+                self.columns.remove(i);
+            }
+            return;
+        }
+        match output_table::parse_columns(name) {
+            // New columns go before the message, which is best shown last.
+            Ok(mut column) => {
+                let i = self
+                    .columns
+                    .iter()
+                    .position(|column| column.name() == "message")
+                    .unwrap_or(self.columns.len());
+                self.columns.insert(i, column.remove(0));
+            }
+            Err(e) => self.message = Some(e.to_string()),
+        }
+    }
+
+    // Handles a key press, returns false to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        self.message = None;
+        if self.input.is_some() {
+            self.handle_input_key(key);
+            return true;
+        }
+
+        match self.view {
+            View::List if self.detail.as_ref().is_some_and(|detail| detail.focused) => {
+                return self.handle_detail_key(key)
+            }
+            View::List => return self.handle_list_key(key),
+            View::Columns(cursor) => {
+                let candidates = self.column_candidates();
+                match key.code {
+                    KeyCode::Esc | KeyCode::Char('c') | KeyCode::Char('q') => {
+                        self.view = View::List
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        self.view = View::Columns((cursor + 1).min(candidates.len() - 1))
+                    }
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        self.view = View::Columns(cursor.saturating_sub(1))
+                    }
+                    KeyCode::Enter | KeyCode::Char(' ') => self.toggle_column(&candidates[cursor]),
+                    _ => {}
+                }
+            }
+        }
+        true
+    }
+
+    fn handle_list_key(&mut self, key: KeyEvent) -> bool {
+        let page = self.height.max(1) as isize;
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Esc if self.detail.is_some() => self.detail = None,
+            KeyCode::Esc => self.search = None,
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.move_by(page),
+            KeyCode::PageUp | KeyCode::Char('b') => self.move_by(-page),
+            KeyCode::Home | KeyCode::Char('g') => self.move_by(isize::MIN),
+            KeyCode::End | KeyCode::Char('G') => self.move_by(isize::MAX),
+            KeyCode::Char('F') => {
+                self.follow = !self.follow;
+                self.refresh();
+            }
+            KeyCode::Char('n') => self.search_next(false),
+            KeyCode::Char('N') => self.search_next(true),
+            KeyCode::Char('/') => self.open(Prompt::Search { backward: false }, ""),
+            KeyCode::Char('?') => self.open(Prompt::Search { backward: true }, ""),
+            KeyCode::Char('f') => self.open(Prompt::Filter, &self.filter_text.clone()),
+            KeyCode::Char('t') => self.open(Prompt::Time, ""),
+            KeyCode::Char('c') => self.view = View::Columns(0),
+            KeyCode::Enter | KeyCode::Tab | KeyCode::Right | KeyCode::Char('l')
+                if !self.visible.is_empty() =>
+            {
+                let field = self.detail.as_ref().map_or(0, |detail| detail.field);
+                self.detail = Some(Detail {
+                    field,
+                    focused: true,
+                });
+            }
+            _ => {}
+        }
+        true
+    }
+
+    // Keys while the side panel has the focus. Keys it doesn't use go to the list.
+    fn handle_detail_key(&mut self, key: KeyEvent) -> bool {
+        let Some(detail) = &mut self.detail else {
+            return true;
+        };
+        match key.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => self.detail = None,
+            KeyCode::Tab | KeyCode::Left | KeyCode::Char('h') => detail.focused = false,
+            KeyCode::Down | KeyCode::Char('j') => detail.field += 1,
+            KeyCode::Up | KeyCode::Char('k') => detail.field = detail.field.saturating_sub(1),
+            KeyCode::Char('=') => self.pivot(false),
+            KeyCode::Char('!') => self.pivot(true),
+            _ => return self.handle_list_key(key),
+        }
+        self.clamp_detail_field();
+        true
+    }
+
+    fn clamp_detail_field(&mut self) {
+        let Some(index) = self.selected_index() else {
+            return;
+        };
+        let store = self.store.lock().unwrap();
+        let count = detail_fields(&store.record(index)).len();
+        if let Some(detail) = &mut self.detail {
+            detail.field = detail.field.min(count.saturating_sub(1));
+        }
+    }
+
+    // Narrows the filter down to records where the field under the cursor has the same
+    // value as in the selected record, or a different one if `negated`.
+    fn pivot(&mut self, negated: bool) {
+        let (Some(index), Some(detail)) = (self.selected_index(), &self.detail) else {
+            return;
+        };
+        let condition = {
+            let store = self.store.lock().unwrap();
+            let record = store.record(index);
+            let Some((name, field)) = detail_fields(&record).get(detail.field).copied() else {
+                return;
+            };
+            let op = if negated { "!=" } else { "==" };
+            format!(
+                "{} {} {}",
+                filter::quote(name),
+                op,
+                filter::quote(&field.value)
+            )
+        };
+        let text = match parse_filter(&self.filter_text) {
+            // `&&` binds tighter than `||`.
+            Ok(Some(Filter::Or(..))) => format!("({}) && {}", self.filter_text.trim(), condition),
+            Ok(Some(_)) => format!("{} && {}", self.filter_text.trim(), condition),
+            _ => condition,
+        };
+        match Filter::parse(&text) {
+            Ok(filter) => {
+                self.filter_text = text;
+                self.set_filter(Some(filter), Some(index));
+            }
+            Err(e) => self.message = Some(e.to_string()),
+        }
+    }
+
+    fn open(&mut self, prompt: Prompt, text: &str) {
+        self.input = Some(Input {
+            prompt,
+            text: text.to_string(),
+            origin: self.selected_index(),
+        });
+    }
+
+    fn handle_input_key(&mut self, key: KeyEvent) {
+        let Some(input) = &mut self.input else {
+            return;
+        };
+        let prompt = input.prompt;
+        match key.code {
+            KeyCode::Esc => {
+                let origin = input.origin;
+                self.input = None;
+                match prompt {
+                    Prompt::Search { .. } => {
+                        self.search = None;
+                        if let Some(origin) = origin {
+                            self.select_index(origin);
+                        }
+                    }
+                    Prompt::Filter => {
+                        let filter = parse_filter(&self.filter_text).unwrap_or(None);
+                        self.set_filter(filter, origin);
+                    }
+                    Prompt::Time => {}
+                }
+                return;
+            }
+            KeyCode::Enter => {
+                let text = input.text.clone();
+                match prompt {
+                    Prompt::Search { backward } => self.search_backward = backward,
+                    Prompt::Filter => {
+                        if let Err(e) = parse_filter(&text) {
+                            self.message = Some(e.to_string());
+                            return;
+                        }
+                        self.filter_text = text;
+                    }
+                    Prompt::Time => self.jump_to_time(&text),
+                }
+                self.input = None;
+                return;
+            }
+            KeyCode::Backspace => {
+                input.text.pop();
+            }
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                input.text.clear()
+            }
+            KeyCode::Char(c) => input.text.push(c),
+            _ => return,
+        }
+
+        // Search and filter as the text is typed.
+        let text = input.text.clone();
+        let origin = input.origin;
+        match prompt {
+            Prompt::Search { backward } => {
+                if let Some(origin) = origin {
+                    self.select_index(origin);
+                }
+                if text.is_empty() {
+                    self.search = None;
+                    return;
+                }
+                // Case-insensitive unless there's an uppercase letter, like vim's smartcase.
+                let pattern = if text.chars().any(char::is_uppercase) {
+                    text
+                } else {
+                    format!("(?i){}", text)
+                };
+                match Regex::new(&pattern) {
+                    Ok(regex) => {
+                        match self.find(&regex, backward) {
+                            Some(found) => {
+                                self.follow = false;
+                                self.selected = found;
+                            }
+                            None => self.message = Some("Pattern not found".to_string()),
+                        }
+                        self.search = Some(regex);
+                    }
+                    Err(_) => self.message = Some("Incomplete regex".to_string()),
+                }
+            }
+            Prompt::Filter => match parse_filter(&text) {
+                Ok(filter) => self.set_filter(filter, origin),
+                Err(e) => self.message = Some(e.to_string()),
+            },
+            Prompt::Time => {}
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [body, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let (left, panel) = match self.detail {
+            Some(_) => {
+                let [left, panel] =
+                    Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                        .areas(body);
+                (left, Some(panel))
+            }
+            None => (body, None),
+        };
+        let [header, list] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(left);
+        self.height = list.height as usize;
+        self.scroll_into_view();
+        self.clamp_detail_field();
+
+        self.draw_list(frame, header, list);
+        if let Some(panel) = panel {
+            self.draw_detail(frame, panel);
+        }
+        if let View::Columns(cursor) = self.view {
+            self.draw_column_picker(frame, list, cursor);
+        }
+        self.draw_status(frame, status);
+    }
+
+    fn draw_list(&self, frame: &mut Frame, header: Rect, list: Rect) {
+        let store = self.store.lock().unwrap();
+        let end = (self.offset + self.height).min(self.visible.len());
+        let rows: Vec<Vec<String>> = (self.offset..end)
+            .map(|i| {
+                let record = store.record(self.visible[i]);
+                let previous = i
+                    .checked_sub(1)
+                    .and_then(|previous| store.record(self.visible[previous]).timestamp());
+                let context = RenderContext {
+                    time: Some(&self.time),
+                    previous,
+                    ..Default::default()
+                };
+                self.columns
+                    .iter()
+                    .map(|column| {
+                        column
+                            .template()
+                            .render(&record, &context)
+                            .replace(['\n', '\r', '\t'], " ")
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let last = self.columns.len() - 1;
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let width = rows
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([column.name().chars().count()])
+                    .max()
+                    .unwrap_or(0);
+                if i == last {
+                    width
+                } else {
+                    width.min(MAX_COLUMN_WIDTH)
+                }
+            })
+            .collect();
+
+        let names: Vec<String> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| pad(&column.name().to_uppercase(), widths[i], i == last))
+            .collect();
+        let header_style = term_style(self.theme.get("header"));
+        frame.render_widget(
+            Paragraph::new(Line::styled(names.join("  "), header_style)),
+            header,
+        );
+
+        let lines: Vec<Line> = rows
+            .iter()
+            .enumerate()
+            .map(|(row, cells)| {
+                let record = store.record(self.visible[self.offset + row]);
+                let mut spans = Vec::new();
+                for (i, cell) in cells.iter().enumerate() {
+                    if i > 0 {
+                        spans.push(Span::raw("  "));
+                    }
+                    let style = match self.columns[i].name() {
+                        "level" => record
+                            .level()
+                            .map(|level| term_style(self.theme.level(level)))
+                            .unwrap_or_default(),
+                        "timestamp" | "ts" => term_style(self.theme.get("timestamp")),
+                        "source" => term_style(self.theme.get("source")),
+                        _ => TermStyle::default(),
+                    };
+                    spans.extend(self.highlight_search(pad(cell, widths[i], i == last), style));
+                }
+                let line = Line::from(spans);
+                if self.offset + row == self.selected {
+                    line.style(TermStyle::default().add_modifier(Modifier::REVERSED))
+                } else {
+                    line
+                }
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines), list);
+    }
+
+    // Splits `text` so that matches of the search stand out.
+    fn highlight_search(&self, text: String, style: TermStyle) -> Vec<Span<'static>> {
+        let Some(regex) = &self.search else {
+            return vec![Span::styled(text, style)];
+        };
+        let matched = TermStyle::default()
+            .fg(TermColor::Black)
+            .bg(TermColor::Yellow);
+        let mut spans = Vec::new();
+        let mut position = 0;
+        for found in regex.find_iter(&text) {
+            if found.start() == found.end() {
+                continue;
+            }
+            spans.push(Span::styled(
+                text[position..found.start()].to_string(),
+                style,
+            ));
+            spans.push(Span::styled(found.as_str().to_string(), matched));
+            position = found.end();
+        }
+        spans.push(Span::styled(text[position..].to_string(), style));
+        spans
+    }
+
+    fn draw_detail(&self, frame: &mut Frame, area: Rect) {
+        let (Some(index), Some(detail)) = (self.selected_index(), &self.detail) else {
+            return;
+        };
+        let store = self.store.lock().unwrap();
+        let record = store.record(index);
+        let block = Block::bordered().title(format!(
+            " {} · {} ",
+            record.source_name(),
+            record.format_name
+        ));
+        let block = if detail.focused {
+            block.title_bottom(" = add ==  ! add !=  tab back ")
+        } else {
+            block.title_bottom(" tab to pick fields ")
+        };
+        let inner = block.inner(area);
+        let (lines, cursor) = self.detail_lines(&record, detail, inner.width as usize);
+
+        // Keep the whole field under the cursor in view, or at least its first line.
+        let (start, end) = cursor;
+        let height = inner.height as usize;
+        let scroll = end.saturating_sub(height).min(start);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(block)
+                .scroll((scroll.min(u16::MAX as usize) as u16, 0)),
+            area,
+        );
+    }
+
+    // Every field of a record wrapped to `width`, then its stack trace. Also returns which
+    // lines the field under the cursor takes.
+    fn detail_lines(
+        &self,
+        record: &Record,
+        detail: &Detail,
+        width: usize,
+    ) -> (Vec<Line<'static>>, (usize, usize)) {
+        let key_style = term_style(self.theme.get("key"));
+        let fields = detail_fields(record);
+        let name_width = fields
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0);
+        let value_width = width.saturating_sub(name_width + 2).max(1);
+
+        let mut lines = Vec::new();
+        let mut cursor = (0, 0);
+        for (i, (name, field)) in fields.into_iter().enumerate() {
+            let value = match field.kind {
+                Kind::Json => serde_json::from_str::<serde_json::Value>(&field.value)
+                    .and_then(|value| serde_json::to_string_pretty(&value))
+                    .unwrap_or_else(|_| field.value.clone()),
+                _ => field.value.clone(),
+            };
+            let style = if i == detail.field && detail.focused {
+                TermStyle::default().add_modifier(Modifier::REVERSED)
+            } else {
+                TermStyle::default()
+            };
+            let start = lines.len();
+            for (j, value_line) in wrap(&value, value_width).into_iter().enumerate() {
+                let name = if j == 0 { name } else { "" };
+                lines.push(Line::from(vec![
+                    Span::styled(format!("{:<1$}", name, name_width), key_style.patch(style)),
+                    Span::styled("  ", style),
+                    Span::styled(value_line, style),
+                ]));
+            }
+            if i == detail.field {
+                cursor = (start, lines.len());
+            }
+        }
+        if let Some(trace) = record.stacktrace() {
+            lines.push(Line::default());
+            for line in stacktrace::render(&stacktrace::text(trace), &self.stacktrace, None) {
+                for line in wrap(&line, width.max(1)) {
+                    lines.push(Line::raw(line));
+                }
+            }
+        }
+        (lines, cursor)
+    }
+
+    fn draw_column_picker(&self, frame: &mut Frame, area: Rect, cursor: usize) {
+        let candidates = self.column_candidates();
+        let width = candidates
+            .iter()
+            .map(|name| name.chars().count() + 8)
+            .max()
+            .unwrap_or(0)
+            .max(24)
+            .min(area.width as usize) as u16;
+        let height = (candidates.len() as u16 + 2).min(area.height);
+        let popup = Rect::new(
+            area.x + area.width.saturating_sub(width) / 2,
+            area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        );
+        // Keep the cursor in view when there are more fields than lines.
+        let skip = (cursor + 1).saturating_sub(height.saturating_sub(2) as usize);
+        let lines: Vec<Line> = candidates
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .map(|(i, name)| {
+                let shown = self.columns.iter().any(|column| column.name() == name);
+                let line = Line::raw(format!(" [{}] {}", if shown { 'x' } else { ' ' }, name));
+                if i == cursor {
+                    line.style(TermStyle::default().add_modifier(Modifier::REVERSED))
+                } else {
+                    line
+                }
+            })
+            .collect();
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Columns · space toggles ")),
+            popup,
+        );
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let store = self.store.lock().unwrap();
+        let mut right = if self.visible.is_empty() {
+            "0".to_string()
+        } else {
+            format!("{}/{}", self.selected + 1, self.visible.len())
+        };
+        if self.filter.is_some() {
+            right.push_str(&format!(" of {}", store.entries.len()));
+        }
+        if store.errors > 0 {
+            right.push_str(&format!(" · {} unreadable", store.errors));
+        }
+        if self.follow {
+            right.push_str(" · FOLLOW");
+        }
+
+        let left = match (&self.input, &self.message) {
+            (Some(input), _) => {
+                let prompt = match input.prompt {
+                    Prompt::Search { backward: false } => "/",
+                    Prompt::Search { backward: true } => "?",
+                    Prompt::Filter => "filter: ",
+                    Prompt::Time => "time: ",
+                };
+                format!("{}{}█", prompt, input.text)
+            }
+            (None, Some(message)) => message.clone(),
+            (None, None) if !self.filter_text.is_empty() => format!("filter: {}", self.filter_text),
+            (None, None) => store.error.clone().unwrap_or_else(|| HELP.to_string()),
+        };
+
+        let width = area.width as usize;
+        let right_width = right.chars().count();
+        let left = truncate(&left, width.saturating_sub(right_width + 1));
+        let gap = width.saturating_sub(left.chars().count() + right_width);
+        let line = format!("{}{}{}", left, " ".repeat(gap), right);
+        frame.render_widget(
+            Paragraph::new(line).style(TermStyle::default().add_modifier(Modifier::REVERSED)),
+            area,
+        );
+    }
+
+    fn run_loop(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        loop {
+            self.refresh();
+            terminal.draw(|frame| self.draw(frame))?;
+            if !event::poll(REFRESH_INTERVAL)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// Takes over the terminal until the pager is quit.
+pub fn run(mut pager: Pager) -> anyhow::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = pager.run_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+// The fields shown in the side panel: all but the stack trace, which is shown below them.
+fn detail_fields<'a>(record: &'a Record) -> Vec<(&'a str, &'a Field)> {
+    let stacktrace = record.stacktrace().map(|field| field.name.as_str());
+    record
+        .fields
+        .iter()
+        .filter(|(name, _)| Some(name.as_str()) != stacktrace)
+        .map(|(name, field)| (name.as_str(), field))
+        .collect()
+}
+
+// Splits text into lines of at most `width` characters.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            lines.push(String::new());
+        }
+        for chunk in chars.chunks(width) {
+            lines.push(chunk.iter().collect());
+        }
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+// None if `text` is empty, for no filter.
+fn parse_filter(text: &str) -> anyhow::Result<Option<Filter>> {
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    Filter::parse(text).map(Some)
+}
+
+// Parses an absolute time, or a time of day on the day of `reference`. Times without a
+// time zone are in the time zone of `reference`, or UTC.
+fn parse_time(
+    text: &str,
+    reference: Option<DateTime<FixedOffset>>,
+) -> Option<DateTime<FixedOffset>> {
+    let text = text.trim();
+    if let Some(time) = timestamp::parse(text, "rfc3339") {
+        return Some(time);
+    }
+    let offset = reference.map_or(FixedOffset::east_opt(0)?, |reference| *reference.offset());
+
+    let mut naive = [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .into_iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok());
+    if naive.is_none() {
+        naive = NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0));
+    }
+    if naive.is_none() {
+        let date = reference?.date_naive();
+        naive = ["%H:%M:%S%.f", "%H:%M"]
+            .into_iter()
+            .find_map(|format| NaiveTime::parse_from_str(text, format).ok())
+            .map(|time| date.and_time(time));
+    }
+    offset.from_local_datetime(&naive?).single()
+}
+
+fn pad(text: &str, width: usize, last: bool) -> String {
+    if last {
+        return text.to_string();
+    }
+    format!("{:<1$}", truncate(text, width), width)
+}
+
+fn term_style(style: &style::Style) -> TermStyle {
+    let color = |color: &Color| match color.for_terminal() {
+        Color::Named(i) | Color::Indexed(i) => TermColor::Indexed(i),
+        Color::Rgb(r, g, b) => TermColor::Rgb(r, g, b),
+    };
+    let mut term = TermStyle::default();
+    if let Some(fg) = &style.fg {
+        term = term.fg(color(fg));
+    }
+    if let Some(bg) = &style.bg {
+        term = term.bg(color(bg));
+    }
+    for (set, modifier) in [
+        (style.bold, Modifier::BOLD),
+        (style.dim, Modifier::DIM),
+        (style.italic, Modifier::ITALIC),
+        (style.underline, Modifier::UNDERLINED),
+    ] {
+        if set {
+            term = term.add_modifier(modifier);
+        }
+    }
+    term
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pager, PagerOptions, Store};
+    use crate::{
+        config::Stacktrace,
+        output::TestRecord,
+        output_table::{parse_columns, DEFAULT_COLUMNS},
+        theme::Theme,
+        timestamp::TimeOptions,
+    };
+    use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use std::sync::{Arc, Mutex};
+
+    // `\n` is Enter, `\x1b` Esc and `\x15` Ctrl-U.
+    fn type_keys(pager: &mut Pager, keys: &str) {
+        for c in keys.chars() {
+            let key = match c {
+                '\n' => KeyEvent::from(KeyCode::Enter),
+                '\x1b' => KeyEvent::from(KeyCode::Esc),
+                '\x15' => KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL),
+                c => KeyEvent::from(KeyCode::Char(c)),
+            };
+            assert!(pager.handle_key(key));
+        }
+    }
+
+    #[test]
+    fn pager_keys() {
+        let mut store = Store::default();
+        for (ts, level, message) in [
+            ("2024-01-01T10:00:00Z", "info", "starting"),
+            ("2024-01-01T10:05:00Z", "error", "Connection refused"),
+            ("2024-01-01T10:10:00Z", "info", "retrying"),
+            ("2024-01-01T10:15:00Z", "error", "connection refused again"),
+        ] {
+            let record = TestRecord::new(&[("ts", ts), ("level", level), ("message", message)])
+                .timestamp("ts", "rfc3339")
+                .raw(format!("{} {} {}", ts, level, message));
+            store.push(&record.record());
+        }
+
+        let options = PagerOptions {
+            columns: parse_columns(DEFAULT_COLUMNS).unwrap(),
+            filter: None,
+            follow: true,
+            theme: Theme::builtin("none").unwrap(),
+            time: TimeOptions::default(),
+            stacktrace: Stacktrace::default(),
+        };
+        let mut pager = Pager::new(Arc::new(Mutex::new(store)), options).unwrap();
+        pager.refresh();
+        assert_eq!(3, pager.selected);
+
+        // Incremental search, case-insensitive and wrapping around.
+        type_keys(&mut pager, "/conn");
+        assert_eq!(1, pager.selected);
+        type_keys(&mut pager, "\nn");
+        assert_eq!(3, pager.selected);
+        type_keys(&mut pager, "n");
+        assert_eq!(1, pager.selected);
+
+        // The filter applies as it's typed and stays on the selected record.
+        type_keys(&mut pager, "flevel == error");
+        assert_eq!(vec![1, 3], pager.visible);
+        assert_eq!(0, pager.selected);
+        type_keys(&mut pager, "\x1b");
+        assert_eq!(4, pager.visible.len());
+        type_keys(&mut pager, "flevel == info\n");
+        assert_eq!("level == info", pager.filter_text);
+        assert_eq!(vec![0, 2], pager.visible);
+
+        type_keys(&mut pager, "f\x15\n");
+        assert_eq!(4, pager.visible.len());
+
+        type_keys(&mut pager, "t10:07\n");
+        assert_eq!(2, pager.selected);
+        type_keys(&mut pager, "t2024-01-01 11:00\n");
+        assert_eq!(2, pager.selected);
+        assert!(pager.message.is_some());
+
+        type_keys(&mut pager, "c");
+        type_keys(&mut pager, "jj ");
+        let names: Vec<_> = pager.columns.iter().map(|column| column.name()).collect();
+        assert_eq!(vec!["timestamp", "level", "source", "message"], names);
+
+        // Fields of the selected record narrow the filter down.
+        type_keys(&mut pager, "\x1bg\nj!");
+        assert_eq!("level != info", pager.filter_text);
+        assert_eq!(vec![1, 3], pager.visible);
+        type_keys(&mut pager, "j=");
+        assert_eq!(
+            "level != info && message == \"Connection refused\"",
+            pager.filter_text
+        );
+        assert_eq!(vec![1], pager.visible);
+    }
+}
@@ -1,11 +1,38 @@
-use crate::{field::Field, source::SourceType};
+use crate::{
+    config::Fields,
+    field::FieldMap,
+    source::{Source, SourceType},
+};
 use async_trait::async_trait;
 use std::{collections::HashMap, error::Error, fmt::Display};
 
 #[async_trait]
 pub trait Reader {
     fn source_type(&self) -> SourceType;
-    async fn read_fields(&mut self) -> Result<HashMap<String, Field>, ReadError>;
+    // Which fields hold the message, level, timestamp and stacktrace.
+    fn format_fields(&self) -> &Fields;
+    // The input consumed since the last call. After `read_fields` this is the raw record.
+    fn take_raw(&mut self) -> String;
+    async fn read_fields(&mut self) -> Result<FieldMap, ReadError>;
+    // Gives up the source, so that the rest of it can be read by another reader. What the
+    // reader keeps between records is lost, other than what the methods below return.
+    fn into_source(self: Box<Self>) -> Source;
+    // Column names read from the source itself, e.g. a CSV header, which another reader of the
+    // rest of the source wouldn't see.
+    fn source_columns(&self) -> Option<SourceColumns> {
+        None
+    }
+    // Messages split over lines whose last line isn't read yet, by stream, e.g. of CRI logs.
+    fn partial_messages(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceColumns {
+    pub names: Vec<String>,
+    // Whether the source is a W3C extended log, whose directives are read wherever they are.
+    pub w3c: bool,
 }
 
 #[derive(Debug)]
@@ -18,16 +45,10 @@ pub enum ReadError {
 impl PartialEq for ReadError {
     fn eq(&self, other: &Self) -> bool {
         use ReadError::*;
-        match (self, other) {
-            (&ParseFail(_), &ParseFail(_)) => true,
-            (&Internal(_), &Internal(_)) => true,
-            (&Eof, &Eof) => true,
-            _ => false,
-        }
-    }
-
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
+        matches!(
+            (self, other),
+            (&ParseFail(_), &ParseFail(_)) | (&Internal(_), &Internal(_)) | (&Eof, &Eof)
+        )
     }
 }
 
@@ -36,8 +57,8 @@ impl Error for ReadError {}
 impl Display for ReadError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
-            Self::ParseFail(ref e) => format!("parse fail: {}", e.to_string()),
-            Self::Internal(ref e) => format!("internal error: {}", e.to_string()),
+            Self::ParseFail(ref e) => format!("parse fail: {}", e),
+            Self::Internal(ref e) => format!("internal error: {}", e),
             Self::Eof => "no input received (EOF)".to_string(),
         };
         write!(f, "read error: {}", s)
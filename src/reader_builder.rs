@@ -1,24 +1,281 @@
 use crate::{
-    config::{Config, Exclude, Format, Include},
+    config::{Config, Fields, Format, FormatType, PayloadType},
     reader::Reader,
-    reader_json::JsonReader,
-    reader_regex::RegexReader,
-    source::{Source, SourceType, Stdin},
+    reader_cri::CriReader,
+    reader_csv::CsvReader,
+    reader_docker::DockerReader,
+    reader_journal::JournalReader,
+    reader_json::{normalize_fields, JsonParser, JsonReader},
+    reader_regex::{PayloadParser, RegexReader},
+    source::{Encoding, Source, SourceType, Stdin},
+    template::Template,
 };
 use anyhow::{anyhow, bail};
 use regex::Regex;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 use tokio::{
     fs::File,
     io::{self, BufReader},
 };
 
 pub struct ReaderBuilder {
-    config: Config,
+    default_format: Option<String>,
     path_matches: HashMap<String, Regex>,
+    formats: HashMap<String, Arc<CompiledFormat>>,
+}
+
+// A reader along with the name of the format it reads.
+pub struct Input {
+    pub format_name: String,
+    pub reader: Box<dyn Reader + Send>,
+    // Shown as the source of records instead of the path.
+    pub label: Option<String>,
+}
+
+impl Input {
+    // What the source is shown as.
+    pub fn source_name(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => self.reader.source_type().to_string(),
+        }
+    }
+}
+
+// A source to read and how to read it.
+#[derive(Debug, Default, PartialEq)]
+pub struct SourceSpec {
+    // A file, or `stdin`.
+    pub path: String,
+    // Found with `path_matches` or `default_format` when not set.
+    pub format: Option<String>,
+    pub label: Option<String>,
+    pub encoding: Encoding,
+}
+
+impl SourceSpec {
+    // `--source path=./a.log,format=nginx,label=edge,encoding=latin1`. `path=` can be left out
+    // of the first option. Anything up to the next `,<option>=` is the value, so values can
+    // have commas.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        const OPTIONS: [&str; 4] = ["path", "format", "label", "encoding"];
+        let mut options: Vec<(&str, String)> = Vec::new();
+        for (i, part) in spec.split(',').enumerate() {
+            match part.split_once('=') {
+                Some((name, value)) if OPTIONS.contains(&name) => {
+                    options.push((name, value.to_string()))
+                }
+                _ if i == 0 => options.push(("path", part.to_string())),
+                _ => match options.last_mut() {
+                    Some((_, value)) => {
+                        value.push(',');
+                        value.push_str(part);
+                    }
+                    None => unreachable!(),
+                },
+            }
+        }
+
+        let mut source = Self::default();
+        for (name, value) in options {
+            match name {
+                "path" => source.path = value,
+                "format" => source.format = Some(value),
+                "label" => source.label = Some(value),
+                _ => source.encoding = Encoding::parse(&value)?,
+            }
+        }
+        if source.path.is_empty() {
+            bail!("source `{}` has no path", spec);
+        }
+        Ok(source)
+    }
+}
+
+// A format of the config, compiled once when the config is loaded and then shared by every
+// reader of it: any number of files (and stdin) can be read with the same format at once.
+pub struct CompiledFormat {
+    // The format's fields, with JSON selectors replaced by the names of the fields they make.
+    fields: Fields,
+    parser: FormatParser,
+    // The fields records can have, if that can be known without reading any.
+    known_fields: Option<Vec<String>>,
+    template: Option<Template>,
+}
+
+enum FormatParser {
+    Json(Arc<JsonParser>),
+    Regex {
+        re: Regex,
+        payload: Option<(String, Arc<PayloadParser>)>,
+    },
+    Cri,
+    Docker,
+    Csv {
+        delimiter: u8,
+        quote: u8,
+        header: Option<bool>,
+        columns: Option<Vec<String>>,
+    },
+    Journal,
+}
+
+impl CompiledFormat {
+    pub fn compile(format: &Format) -> anyhow::Result<Self> {
+        let parser = match &format.format_type {
+            FormatType::JsonFormat { fields } => {
+                FormatParser::Json(Arc::new(JsonParser::new(fields.clone())?))
+            }
+            FormatType::RegexFormat {
+                format,
+                fields,
+                payload,
+            } => {
+                let re = Regex::new(format)
+                    .map_err(|e| anyhow!("regex failed for `{}` because {}", format, e))?;
+                let payload = match payload {
+                    Some(payload) => {
+                        if !re
+                            .capture_names()
+                            .flatten()
+                            .any(|name| name == payload.capture)
+                        {
+                            bail!(
+                                "payload capture `{}` is not a named capture of `{}`",
+                                payload.capture,
+                                format
+                            );
+                        }
+                        let parser = match payload.payload_type {
+                            PayloadType::Json => {
+                                PayloadParser::Json(JsonParser::new(fields.clone())?)
+                            }
+                            PayloadType::Logfmt => {
+                                PayloadParser::Logfmt(JsonParser::new(fields.clone())?)
+                            }
+                        };
+                        Some((payload.capture.clone(), Arc::new(parser)))
+                    }
+                    None => None,
+                };
+                FormatParser::Regex { re, payload }
+            }
+            FormatType::CriFormat { .. } => FormatParser::Cri,
+            FormatType::DockerFormat { .. } => FormatParser::Docker,
+            FormatType::CsvFormat {
+                delimiter,
+                quote,
+                header,
+                columns,
+                ..
+            } => {
+                if !delimiter.is_ascii() || !quote.is_ascii() {
+                    bail!("csv delimiter and quote must be ASCII characters");
+                }
+                FormatParser::Csv {
+                    delimiter: *delimiter as u8,
+                    quote: *quote as u8,
+                    header: *header,
+                    columns: columns.clone(),
+                }
+            }
+            FormatType::JournalFormat { .. } => FormatParser::Journal,
+        };
+
+        let fields = match &parser {
+            FormatParser::Json(_) => normalize_fields(format.format_type.fields())?,
+            _ => format.format_type.fields().clone(),
+        };
+        let known_fields = ReaderBuilder::format_known_fields(&format.format_type);
+        let template = match &format.template {
+            Some(template) => {
+                let template = Template::parse(template)?;
+                template.validate(format.format_type.fields(), known_fields.as_deref())?;
+                Some(template)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            fields,
+            parser,
+            known_fields,
+            template,
+        })
+    }
+
+    pub fn reader(&self, source: Source) -> Box<dyn Reader + Send> {
+        let fields = self.fields.clone();
+        match &self.parser {
+            FormatParser::Json(parser) => Box::new(JsonReader::new(source, fields, parser.clone())),
+            FormatParser::Regex { re, payload } => {
+                let reader = RegexReader::new(source, re.clone(), fields);
+                match payload {
+                    Some((capture, parser)) => {
+                        Box::new(reader.with_payload(capture.clone(), parser.clone()))
+                    }
+                    None => Box::new(reader),
+                }
+            }
+            FormatParser::Cri => Box::new(CriReader::new(source, fields)),
+            FormatParser::Docker => Box::new(DockerReader::new(source, fields)),
+            FormatParser::Csv {
+                delimiter,
+                quote,
+                header,
+                columns,
+            } => Box::new(CsvReader::new(
+                source,
+                *delimiter,
+                *quote,
+                *header,
+                columns.clone(),
+                fields,
+            )),
+            FormatParser::Journal => Box::new(JournalReader::new(source, fields)),
+        }
+    }
+
+    // A reader of the rest of `previous`'s source, e.g. after the config is reloaded. Columns
+    // `previous` read from a CSV header carry over, as the header isn't read again, and so do
+    // CRI and Docker messages it read the first lines of.
+    pub fn resume(&self, previous: Box<dyn Reader + Send>) -> Box<dyn Reader + Send> {
+        let source_columns = previous.source_columns();
+        let partial = previous.partial_messages();
+        let source = previous.into_source();
+        match (&self.parser, source_columns) {
+            (
+                FormatParser::Csv {
+                    delimiter,
+                    quote,
+                    header,
+                    columns,
+                },
+                Some(source_columns),
+            ) => Box::new(
+                CsvReader::new(
+                    source,
+                    *delimiter,
+                    *quote,
+                    *header,
+                    columns.clone(),
+                    self.fields.clone(),
+                )
+                .with_source_columns(source_columns),
+            ),
+            (FormatParser::Cri, _) => {
+                Box::new(CriReader::new(source, self.fields.clone()).with_partial_messages(partial))
+            }
+            (FormatParser::Docker, _) => Box::new(
+                DockerReader::new(source, self.fields.clone()).with_partial_messages(partial),
+            ),
+            _ => self.reader(source),
+        }
+    }
 }
 
 impl ReaderBuilder {
+    // Compiles every format, so that a broken one is reported even if no source uses it.
     pub fn new(config: Config) -> anyhow::Result<Self> {
         let mut path_matches = HashMap::new();
         for (format_name, match_regex) in &config.path_matches {
@@ -32,64 +289,124 @@ impl ReaderBuilder {
             path_matches.insert(format_name.clone(), re);
         }
 
+        let mut formats = HashMap::new();
+        for (format_name, format) in &config.formats {
+            let compiled = CompiledFormat::compile(format)
+                .map_err(|e| anyhow!("format `{}`: {}", format_name, e))?;
+            formats.insert(format_name.clone(), Arc::new(compiled));
+        }
+
         Ok(Self {
-            config: config,
-            path_matches: path_matches,
+            default_format: config.default_format,
+            path_matches,
+            formats,
         })
     }
 
-    pub async fn build(
-        &mut self,
-        sources: Vec<String>,
-    ) -> anyhow::Result<Vec<Box<dyn Reader + Send>>> {
-        let mut readers = Vec::with_capacity(sources.len());
+    // Templates of formats, by format name. They are validated when the builder is created.
+    pub fn templates(&self) -> HashMap<String, Template> {
+        self.formats
+            .iter()
+            .filter_map(|(name, format)| Some((name.clone(), format.template.clone()?)))
+            .collect()
+    }
+
+    // The fields records of a format can have, if that can be known without reading any.
+    pub fn known_fields(&self, format_name: &str) -> Option<&[String]> {
+        self.formats.get(format_name)?.known_fields.as_deref()
+    }
+
+    pub fn format_known_fields(format_type: &FormatType) -> Option<Vec<String>> {
+        match format_type {
+            FormatType::RegexFormat {
+                format,
+                payload: None,
+                ..
+            } => Regex::new(format).ok().map(|re| {
+                re.capture_names()
+                    .flatten()
+                    .map(|name| name.to_string())
+                    .collect()
+            }),
+            FormatType::CsvFormat {
+                columns: Some(columns),
+                ..
+            } => Some(columns.clone()),
+            FormatType::CriFormat { .. } => Some(
+                [
+                    "time",
+                    "stream",
+                    "message",
+                    "pod",
+                    "namespace",
+                    "container",
+                    "container_id",
+                ]
+                .map(String::from)
+                .to_vec(),
+            ),
+            _ => None,
+        }
+    }
+
+    // A path given by itself. `<format>:<path>`, e.g. `nginx:data/log/access.log`, reads the
+    // path with that format, if there's a format with that name.
+    pub fn source_spec(&self, arg: &str) -> SourceSpec {
+        let (format, path) = match arg.split_once(':') {
+            Some((format, path)) if self.formats.contains_key(format) => {
+                (Some(format.to_string()), path)
+            }
+            _ => (None, arg),
+        };
+        SourceSpec {
+            path: path.to_string(),
+            format,
+            ..Default::default()
+        }
+    }
+
+    pub async fn build(&mut self, sources: Vec<SourceSpec>) -> anyhow::Result<Vec<Input>> {
+        let mut inputs = Vec::with_capacity(sources.len());
         let mut stdin_used = false;
 
         for source in sources {
-            let (reader, _stdin_used) = self.build_one(source).await?;
-            if stdin_used == false {
+            let (input, _stdin_used) = self.build_one(source).await?;
+            if !stdin_used {
                 stdin_used = _stdin_used;
             }
-            readers.push(reader);
+            inputs.push(input);
         }
 
-        println!("stdin_used: {stdin_used}");
-
         // If there's an stdin input but there's no stdin found.
         if !stdin_used && atty::isnt(atty::Stream::Stdin) {
             let stdin = io::stdin();
             let stdin = BufReader::new(Stdin::new(stdin));
             let source = Source::new(SourceType::Stdin, stdin);
 
-            let format = match &self.config.default_format {
-                Some(format) => self.find_format(&format.clone())?,
+            let format_name = match &self.default_format {
+                Some(format) => format.clone(),
                 None => bail!("stdin is used but there's no format defined for it"),
             };
+            let format = self.find_format(&format_name)?;
 
-            readers.push(self.new_reader(source, format)?);
+            inputs.push(Input {
+                reader: format.reader(source),
+                format_name,
+                label: None,
+            });
         }
 
-        Ok(readers)
+        Ok(inputs)
     }
 
-    async fn build_one(
-        &mut self,
-        source: String,
-    ) -> anyhow::Result<(Box<dyn Reader + Send>, bool)> {
+    async fn build_one(&mut self, spec: SourceSpec) -> anyhow::Result<(Input, bool)> {
         let mut stdin_used = false;
 
-        // Format: <format>:<file_path>
-        // e.g. nginx:data/log/access.log
-        let (format_name, file_path) = if let Some(semicolon_loc) = source.find(":") {
-            let format_name = source[..semicolon_loc].to_string();
-            let file_path = &source[semicolon_loc + 1..];
-            (format_name, file_path)
-        } else {
-            let file_path = source.as_str();
-            let format_name = self.format_name_from_file_path(file_path)?;
-            (format_name, file_path)
+        let file_path = spec.path.as_str();
+        let format_name = match spec.format {
+            Some(format_name) => format_name,
+            None => self.format_name_from_file_path(file_path)?,
         };
-
         let format = self.find_format(&format_name)?;
 
         let source = if file_path == "stdin" {
@@ -102,30 +419,19 @@ impl ReaderBuilder {
             let stdin = BufReader::new(Stdin::new(stdin));
             Source::new(SourceType::Stdin, stdin)
         } else {
-            println!("{file_path}");
-            let file = File::open(file_path).await?;
+            let file = File::open(file_path)
+                .await
+                .map_err(|e| anyhow!("{}: {}", file_path, e))?;
             let source = BufReader::new(file);
             Source::new(SourceType::File(file_path.to_string()), source)
         };
 
-        Ok((self.new_reader(source, format)?, stdin_used))
-    }
-
-    fn new_reader(&self, source: Source, format: Format) -> anyhow::Result<Box<dyn Reader + Send>> {
-        let reader: Box<dyn Reader + Send> = match format {
-            Format::JsonFormat { exclude, include } => {
-                let exclude = exclude.unwrap_or(Exclude::ExcludeMany(Vec::new()));
-                let include = include.unwrap_or(Include::IncludeMany(Vec::new()));
-                Box::new(JsonReader::new(source, exclude, include))
-            }
-            Format::RegexFormat { format } => {
-                let re = Regex::new(&format).map_err(|e| {
-                    anyhow!("regex failed for `{}` because {}", format, e.to_string())
-                })?;
-                Box::new(RegexReader::new(source, re))
-            }
+        let input = Input {
+            reader: format.reader(source.with_encoding(spec.encoding)),
+            format_name,
+            label: spec.label,
         };
-        Ok(reader)
+        Ok((input, stdin_used))
     }
 
     fn format_name_from_file_path(&self, file_path: &str) -> anyhow::Result<String> {
@@ -135,8 +441,8 @@ impl ReaderBuilder {
             }
         }
 
-        match &self.config.default_format {
-            Some(ref format) => return Ok(format.clone()),
+        match &self.default_format {
+            Some(ref format) => Ok(format.clone()),
             None => {
                 bail!(
                     "no path matches found for {}. there is no default_format set either. exiting.",
@@ -146,11 +452,113 @@ impl ReaderBuilder {
         }
     }
 
-    fn find_format(&mut self, format_name: &str) -> anyhow::Result<Format> {
-        // Used `remove` to take ownership.
-        match self.config.formats.remove(format_name) {
-            Some(format) => Ok(format),
+    pub fn find_format(&self, format_name: &str) -> anyhow::Result<Arc<CompiledFormat>> {
+        match self.formats.get(format_name) {
+            Some(format) => Ok(format.clone()),
             None => bail!("there's no format with name: {}", format_name),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ReaderBuilder, SourceSpec};
+    use crate::{
+        config::Config,
+        source::{Encoding, Source, SourceType},
+    };
+    use std::{io::Cursor, sync::Arc};
+    use tokio::io::BufReader;
+
+    #[tokio::test]
+    async fn shared_format() {
+        let builder = ReaderBuilder::new(Config::sample()).unwrap();
+        let format = builder.find_format("container_json").unwrap();
+        assert!(Arc::ptr_eq(
+            &format,
+            &builder.find_format("container_json").unwrap()
+        ));
+
+        let source = |name: &str, line: &str| {
+            Source::new(
+                SourceType::File(name.to_string()),
+                BufReader::new(Cursor::new(line.to_string())),
+            )
+        };
+        let mut a = format.reader(source("a", "t stdout F {\"msg\":\"a\"}"));
+        let mut b = format.reader(source("b", "t stderr F {\"msg\":\"b\"}"));
+        let (a, b) = tokio::join!(a.read_fields(), b.read_fields());
+        assert_eq!("a", a.unwrap().get("msg").unwrap().value);
+        assert_eq!("stderr", b.unwrap().get("stream").unwrap().value);
+    }
+
+    #[tokio::test]
+    async fn source_specs() {
+        let spec =
+            SourceSpec::parse("path=logs/a,b.log,label=edge,encoding=latin1,format=nginx").unwrap();
+        assert_eq!(
+            SourceSpec {
+                path: "logs/a,b.log".to_string(),
+                format: Some("nginx".to_string()),
+                label: Some("edge".to_string()),
+                encoding: Encoding::Latin1,
+            },
+            spec
+        );
+        assert_eq!("a.log", SourceSpec::parse("a.log,label=x").unwrap().path);
+        assert!(SourceSpec::parse("format=nginx").is_err());
+        assert!(SourceSpec::parse("a.log,encoding=ebcdic").is_err());
+
+        let builder = ReaderBuilder::new(Config::sample()).unwrap();
+        let spec = builder.source_spec("cri:pod.log");
+        assert_eq!(
+            ("pod.log", Some("cri")),
+            (spec.path.as_str(), spec.format.as_deref())
+        );
+        // Not a format, so a path with a colon.
+        let spec = builder.source_spec("logs/10:00.log");
+        assert_eq!(("logs/10:00.log", None), (spec.path.as_str(), spec.format));
+
+        let format = builder.find_format("container_json").unwrap();
+        let source = Source::new(
+            SourceType::File("a".to_string()),
+            BufReader::new(Cursor::new(b"t stdout F caf\xe9".to_vec())),
+        );
+        let mut reader = format.reader(source.with_encoding(Encoding::Latin1));
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("café", fields.get("payload").unwrap().value);
+    }
+
+    #[tokio::test]
+    async fn resumed_reader() {
+        let builder = ReaderBuilder::new(Config::sample()).unwrap();
+        let source = Source::new(
+            SourceType::File("batch.csv".to_string()),
+            BufReader::new(Cursor::new("time,job\n1,backup\n2,cleanup\n")),
+        );
+        let mut reader = builder.find_format("batch_csv").unwrap().reader(source);
+        assert_eq!("backup", reader.read_fields().await.unwrap()["job"].value);
+        reader.take_raw();
+
+        // As if the config was reloaded: the header isn't read again.
+        let builder = ReaderBuilder::new(Config::sample()).unwrap();
+        let mut reader = builder.find_format("batch_csv").unwrap().resume(reader);
+        assert_eq!("cleanup", reader.read_fields().await.unwrap()["job"].value);
+        assert_eq!("2,cleanup\n", reader.take_raw());
+
+        // A CRI message is joined from both readers' lines.
+        let source = Source::new(
+            SourceType::Stdin,
+            BufReader::new(Cursor::new(
+                "t stdout P hel\nt stderr F oops\nt stdout F lo\n",
+            )),
+        );
+        let mut reader = builder.find_format("cri").unwrap().reader(source);
+        assert_eq!("oops", reader.read_fields().await.unwrap()["message"].value);
+        let mut reader = builder.find_format("cri").unwrap().resume(reader);
+        assert_eq!(
+            "hello",
+            reader.read_fields().await.unwrap()["message"].value
+        );
+    }
+}
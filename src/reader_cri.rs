@@ -0,0 +1,145 @@
+use crate::{
+    config::Fields,
+    field::{Field, FieldFilter, FieldMap},
+    kubernetes,
+    reader::{ReadError, Reader},
+    source::{Source, SourceType},
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+// Reads the CRI log format written by containerd and CRI-O:
+// `2016-10-06T00:17:09.669794202Z stdout F log line`
+// Partial lines (tag `P`) are joined until the final line (tag `F`) of the same stream.
+pub struct CriReader {
+    source: Source,
+    fields: Fields,
+    filter: FieldFilter,
+    metadata: FieldMap,
+    partial: HashMap<String, String>,
+}
+
+impl CriReader {
+    pub fn new(source: Source, fields: Fields) -> Self {
+        let metadata = match source.source_type() {
+            SourceType::File(path) => kubernetes::metadata_from_path(&path),
+            SourceType::Stdin => FieldMap::new(),
+        };
+
+        Self {
+            source,
+            filter: FieldFilter::new(&fields),
+            fields,
+            metadata,
+            partial: HashMap::new(),
+        }
+    }
+
+    // Goes on with messages another reader of the same source read part of.
+    pub fn with_partial_messages(mut self, partial: HashMap<String, String>) -> Self {
+        self.partial = partial;
+        self
+    }
+}
+
+#[async_trait]
+impl Reader for CriReader {
+    fn source_type(&self) -> SourceType {
+        self.source.source_type()
+    }
+
+    fn format_fields(&self) -> &Fields {
+        &self.fields
+    }
+
+    fn take_raw(&mut self) -> String {
+        self.source.take_raw()
+    }
+
+    fn into_source(self: Box<Self>) -> Source {
+        self.source
+    }
+
+    fn partial_messages(&self) -> HashMap<String, String> {
+        self.partial.clone()
+    }
+
+    async fn read_fields(&mut self) -> Result<FieldMap, ReadError> {
+        while let Some(line) = self.source.read_line().await {
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            let mut parts = line.splitn(4, ' ');
+            let (time, stream, tag) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(time), Some(stream), Some(tag)) => (time, stream, tag),
+                _ => return Err(ReadError::ParseFail(anyhow!("not a CRI log line"))),
+            };
+            let content = parts.next().unwrap_or("");
+
+            // The tag can carry more flags separated by `:`. Only the first one is defined.
+            if tag.split(':').next() == Some("P") {
+                self.partial
+                    .entry(stream.to_string())
+                    .or_default()
+                    .push_str(content);
+                continue;
+            }
+
+            let message = match self.partial.remove(stream) {
+                Some(mut partial) => {
+                    partial.push_str(content);
+                    partial
+                }
+                None => content.to_string(),
+            };
+
+            let mut map = FieldMap::with_capacity(3 + self.metadata.len());
+            for (name, value) in [("time", time), ("stream", stream), ("message", &message)] {
+                map.insert(
+                    name.to_string(),
+                    Field::new(name.to_string(), value.to_string()),
+                );
+            }
+            map.extend(self.metadata.clone());
+            self.filter.apply(&mut map);
+            return Ok(map);
+        }
+        Err(ReadError::Eof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use tokio::io::BufReader;
+
+    use crate::{reader::Reader, source::Source};
+
+    use super::CriReader;
+
+    #[tokio::test]
+    async fn cri_reader() {
+        let c = Cursor::new(
+            "2024-01-01T00:00:00Z stdout P hello \n\
+             2024-01-01T00:00:00Z stderr F oops\n\
+             2024-01-01T00:00:01Z stdout F world\n",
+        );
+
+        let source = BufReader::new(c);
+        let path = "/var/log/containers/web-7d4b9_default_nginx-0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef.log";
+        let source = Source::new(crate::source::SourceType::File(path.to_string()), source);
+        let mut reader = CriReader::new(source, Default::default());
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("stderr", fields.get("stream").unwrap().value);
+        assert_eq!("oops", fields.get("message").unwrap().value);
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("stdout", fields.get("stream").unwrap().value);
+        assert_eq!("hello world", fields.get("message").unwrap().value);
+        assert_eq!("2024-01-01T00:00:01Z", fields.get("time").unwrap().value);
+        assert_eq!("web-7d4b9", fields.get("pod").unwrap().value);
+        assert_eq!("default", fields.get("namespace").unwrap().value);
+        assert_eq!("nginx", fields.get("container").unwrap().value);
+    }
+}
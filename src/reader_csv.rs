@@ -0,0 +1,308 @@
+use crate::{
+    config::Fields,
+    field::{Field, FieldFilter, FieldMap},
+    reader::{ReadError, Reader, SourceColumns},
+    source::{Source, SourceType},
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+
+// Reads delimited logs (CSV, TSV, W3C extended log format) one row per record. A quoted value
+// can span lines.
+//
+// Column names come from, in order of precedence:
+// - a W3C `#Fields:` directive, which can appear anywhere and redefines the columns,
+// - explicit `columns` from the config,
+// - the header row, if `header` is set or the first row looks like a header.
+// Unnamed columns are called `column1`, `column2`, ...
+//
+// `#...:` lines are W3C directives only if neither `header` nor `columns` is set and the source
+// starts with one. Otherwise they're rows like any other.
+pub struct CsvReader {
+    source: Source,
+    delimiter: u8,
+    quote: u8,
+    header: Option<bool>,
+    columns: Option<Vec<String>>,
+    first_row: bool,
+    // Set if `columns` were read from the source rather than given by the config.
+    columns_read: bool,
+    // Whether the source is a W3C extended log. Not known until the first line is read.
+    w3c: Option<bool>,
+    // The lines read so far of a row whose quoted value isn't closed yet.
+    pending: String,
+    fields: Fields,
+    filter: FieldFilter,
+}
+
+impl CsvReader {
+    pub fn new(
+        source: Source,
+        delimiter: u8,
+        quote: u8,
+        header: Option<bool>,
+        columns: Option<Vec<String>>,
+        fields: Fields,
+    ) -> Self {
+        Self {
+            source,
+            delimiter,
+            quote,
+            header,
+            columns,
+            first_row: true,
+            columns_read: false,
+            w3c: None,
+            pending: String::new(),
+            filter: FieldFilter::new(&fields),
+            fields,
+        }
+    }
+
+    // Reads the rest of a source whose columns another reader already read.
+    pub fn with_source_columns(mut self, columns: SourceColumns) -> Self {
+        self.columns = Some(columns.names);
+        self.first_row = false;
+        self.columns_read = true;
+        self.w3c = Some(columns.w3c);
+        self
+    }
+
+    // Whether `row` ends inside a quoted value, i.e. the row goes on in the next line.
+    fn unterminated(&self, row: &str) -> bool {
+        let mut quoted = false;
+        // A quote opens a quoted value at the start of a value, or right after a closing quote
+        // where it's an escaped quote.
+        let mut can_open = true;
+        for b in row.bytes() {
+            if quoted {
+                if b == self.quote {
+                    quoted = false;
+                    can_open = true;
+                    continue;
+                }
+            } else if b == self.quote && can_open {
+                quoted = true;
+                continue;
+            }
+            can_open = b == self.delimiter;
+        }
+        quoted
+    }
+
+    fn split(&self, line: &str) -> Result<Vec<String>, ReadError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .from_reader(line.as_bytes());
+
+        match reader.records().next() {
+            Some(record) => Ok(record
+                .map_err(|e| ReadError::ParseFail(anyhow::Error::new(e)))?
+                .iter()
+                .map(|value| value.to_string())
+                .collect()),
+            None => Err(ReadError::ParseFail(anyhow!("empty row"))),
+        }
+    }
+}
+
+// A header has no empty or numeric cells and no duplicates.
+fn looks_like_header(values: &[String]) -> bool {
+    values.iter().enumerate().all(|(i, value)| {
+        !value.is_empty() && value.parse::<f64>().is_err() && !values[..i].contains(value)
+    })
+}
+
+// W3C directives look like `#Version: 1.0` or `#Fields: date time cs-method`.
+fn parse_directive(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = line.strip_prefix('#')?.split_once(':')?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic() || c == '-') {
+        return None;
+    }
+    Some((name, value.trim()))
+}
+
+#[async_trait]
+impl Reader for CsvReader {
+    fn source_type(&self) -> SourceType {
+        self.source.source_type()
+    }
+
+    fn format_fields(&self) -> &Fields {
+        &self.fields
+    }
+
+    fn take_raw(&mut self) -> String {
+        self.source.take_raw()
+    }
+
+    fn into_source(self: Box<Self>) -> Source {
+        self.source
+    }
+
+    fn source_columns(&self) -> Option<SourceColumns> {
+        let w3c = self.w3c == Some(true);
+        let names = match &self.columns {
+            Some(columns) if self.columns_read => columns.clone(),
+            _ if w3c => Vec::new(),
+            _ => return None,
+        };
+        Some(SourceColumns { names, w3c })
+    }
+
+    // A row that isn't finished yet is kept until more input arrives.
+    async fn read_fields(&mut self) -> Result<FieldMap, ReadError> {
+        while let Some(mut line) = self.source.read_line().await {
+            let continued = !self.pending.is_empty();
+            if continued {
+                line = std::mem::take(&mut self.pending) + &line;
+            }
+            let row = line.trim_end_matches(['\n', '\r']);
+            if row.is_empty() {
+                self.source.take_raw();
+                continue;
+            }
+
+            if !continued {
+                let w3c = *self.w3c.get_or_insert_with(|| {
+                    self.header.is_none()
+                        && self.columns.is_none()
+                        && parse_directive(row).is_some()
+                });
+                if w3c {
+                    self.first_row = false;
+                    if let Some((name, value)) = parse_directive(row) {
+                        if name == "Fields" {
+                            self.columns =
+                                Some(value.split_whitespace().map(String::from).collect());
+                            self.columns_read = true;
+                        }
+                        self.source.take_raw();
+                        continue;
+                    }
+                }
+            }
+
+            if self.unterminated(row) {
+                self.pending = line;
+                continue;
+            }
+
+            let values = self.split(row)?;
+
+            if self.first_row {
+                self.first_row = false;
+                let header = self
+                    .header
+                    .unwrap_or_else(|| self.columns.is_none() && looks_like_header(&values));
+                if header {
+                    if self.columns.is_none() {
+                        self.columns = Some(values);
+                        self.columns_read = true;
+                    }
+                    self.source.take_raw();
+                    continue;
+                }
+            }
+
+            let mut map = FieldMap::with_capacity(values.len());
+            for (i, value) in values.into_iter().enumerate() {
+                let name = match self.columns.as_ref().and_then(|columns| columns.get(i)) {
+                    Some(name) => name.clone(),
+                    None => format!("column{}", i + 1),
+                };
+                map.insert(name.clone(), Field::new(name, value));
+            }
+            self.filter.apply(&mut map);
+            return Ok(map);
+        }
+        Err(ReadError::Eof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use tokio::io::BufReader;
+
+    use crate::{
+        config::{Exclude, Fields},
+        reader::Reader,
+        source::Source,
+    };
+
+    use super::CsvReader;
+
+    fn source(text: &'static str) -> Source {
+        let source = BufReader::new(Cursor::new(text));
+        Source::new(crate::source::SourceType::File("test".to_string()), source)
+    }
+
+    #[tokio::test]
+    async fn csv_reader_w3c() {
+        let c = Cursor::new(
+            "#Software: Microsoft Internet Information Services 10.0\n\
+             #Fields: date time cs-method cs-uri-stem sc-status\n\
+             2024-01-01 00:00:00 GET /index.html 200\n\
+             #Fields: date time sc-status\n\
+             2024-01-01 00:00:01 404\n",
+        );
+
+        let source = BufReader::new(c);
+        let source = Source::new(crate::source::SourceType::File("test".to_string()), source);
+        let mut reader = CsvReader::new(source, b' ', b'"', None, None, Default::default());
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("GET", fields.get("cs-method").unwrap().value);
+        assert_eq!("200", fields.get("sc-status").unwrap().value);
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("404", fields.get("sc-status").unwrap().value);
+        assert!(!fields.contains_key("cs-method"));
+    }
+
+    #[tokio::test]
+    async fn csv_reader_rows() {
+        let fields = Fields {
+            exclude: Some(Exclude::ExcludeMany(vec!["secret".to_string()])),
+            ..Default::default()
+        };
+        let mut reader = CsvReader::new(
+            source(
+                "time,msg,secret\n\
+                 1,\"two\nlines, \"\"quoted\"\"\",x\n\
+                 #2:00,\"not a directive\",y\n",
+            ),
+            b',',
+            b'"',
+            None,
+            None,
+            fields,
+        );
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("two\nlines, \"quoted\"", fields.get("msg").unwrap().value);
+        assert!(!fields.contains_key("secret"));
+        assert_eq!("1,\"two\nlines, \"\"quoted\"\"\",x\n", reader.take_raw());
+
+        // Only a source starting with a directive is a W3C log.
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("#2:00", fields.get("time").unwrap().value);
+        assert_eq!("not a directive", fields.get("msg").unwrap().value);
+
+        // Nor is one with columns or a header set in the config.
+        let mut reader = CsvReader::new(
+            source("#Fields: a b\n1 2\n"),
+            b' ',
+            b'"',
+            Some(false),
+            None,
+            Default::default(),
+        );
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("#Fields:", fields.get("column1").unwrap().value);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{
+    config::Fields,
+    field::{Field, FieldFilter, FieldMap},
+    kubernetes,
+    reader::{ReadError, Reader},
+    source::{Source, SourceType},
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Reads logs written by Docker's json-file logging driver:
+// `{"log":"log line\n","stream":"stdout","time":"2024-01-01T00:00:00.000000000Z"}`
+// Docker splits long lines into several entries, only the last one ending with `\n`.
+// Those are joined back per stream.
+pub struct DockerReader {
+    source: Source,
+    fields: Fields,
+    filter: FieldFilter,
+    metadata: FieldMap,
+    partial: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    log: String,
+    #[serde(default)]
+    stream: String,
+    time: Option<String>,
+    #[serde(default)]
+    attrs: HashMap<String, String>,
+}
+
+impl DockerReader {
+    pub fn new(source: Source, fields: Fields) -> Self {
+        let metadata = match source.source_type() {
+            SourceType::File(path) => kubernetes::metadata_from_path(&path),
+            SourceType::Stdin => FieldMap::new(),
+        };
+
+        Self {
+            source,
+            filter: FieldFilter::new(&fields),
+            fields,
+            metadata,
+            partial: HashMap::new(),
+        }
+    }
+
+    // Goes on with messages another reader of the same source read part of.
+    pub fn with_partial_messages(mut self, partial: HashMap<String, String>) -> Self {
+        self.partial = partial;
+        self
+    }
+}
+
+#[async_trait]
+impl Reader for DockerReader {
+    fn source_type(&self) -> SourceType {
+        self.source.source_type()
+    }
+
+    fn format_fields(&self) -> &Fields {
+        &self.fields
+    }
+
+    fn take_raw(&mut self) -> String {
+        self.source.take_raw()
+    }
+
+    fn into_source(self: Box<Self>) -> Source {
+        self.source
+    }
+
+    fn partial_messages(&self) -> HashMap<String, String> {
+        self.partial.clone()
+    }
+
+    async fn read_fields(&mut self) -> Result<FieldMap, ReadError> {
+        while let Some(line) = self.source.read_line().await {
+            let entry: Entry = serde_json::from_str(line.trim())
+                .map_err(|e| ReadError::ParseFail(anyhow::Error::new(e)))?;
+
+            let log = match entry.log.strip_suffix('\n') {
+                Some(log) => log,
+                None => {
+                    self.partial
+                        .entry(entry.stream)
+                        .or_default()
+                        .push_str(&entry.log);
+                    continue;
+                }
+            };
+
+            let log = match self.partial.remove(&entry.stream) {
+                Some(mut partial) => {
+                    partial.push_str(log);
+                    partial
+                }
+                None => log.to_string(),
+            };
+
+            let mut map = FieldMap::new();
+            let mut insert = |name: String, value: String| {
+                map.insert(name.clone(), Field::new(name, value));
+            };
+            insert("log".to_string(), log);
+            insert("stream".to_string(), entry.stream);
+            if let Some(time) = entry.time {
+                insert("time".to_string(), time);
+            }
+            for (name, value) in entry.attrs {
+                insert(name, value);
+            }
+            map.extend(self.metadata.clone());
+            self.filter.apply(&mut map);
+            return Ok(map);
+        }
+        Err(ReadError::Eof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use tokio::io::BufReader;
+
+    use crate::{
+        config::{Exclude, Fields},
+        reader::Reader,
+        source::Source,
+    };
+
+    use super::DockerReader;
+
+    #[tokio::test]
+    async fn docker_reader() {
+        let c = Cursor::new(
+            "{\"log\":\"hello \",\"stream\":\"stdout\",\"time\":\"2024-01-01T00:00:00Z\"}\n\
+             {\"log\":\"oops\\n\",\"stream\":\"stderr\",\"time\":\"2024-01-01T00:00:00Z\"}\n\
+             {\"log\":\"world\\n\",\"stream\":\"stdout\",\"time\":\"2024-01-01T00:00:01Z\",\"attrs\":{\"tag\":\"web\",\"env\":\"prod\"}}\n",
+        );
+
+        let source = BufReader::new(c);
+        let path = "/var/log/containers/web-7d4b9_default_nginx-0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef.log";
+        let source = Source::new(crate::source::SourceType::File(path.to_string()), source);
+        let fields = Fields {
+            message: Some("log".to_string()),
+            exclude: Some(Exclude::ExcludeMany(vec!["env".to_string()])),
+            ..Default::default()
+        };
+        let mut reader = DockerReader::new(source, fields);
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("stderr", fields.get("stream").unwrap().value);
+        assert_eq!("oops", fields.get("log").unwrap().value);
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("hello world", fields.get("log").unwrap().value);
+        assert_eq!("2024-01-01T00:00:01Z", fields.get("time").unwrap().value);
+        assert_eq!("web", fields.get("tag").unwrap().value);
+        assert!(!fields.contains_key("env"));
+        assert_eq!("web-7d4b9", fields.get("pod").unwrap().value);
+        assert_eq!("default", fields.get("namespace").unwrap().value);
+        assert_eq!("nginx", fields.get("container").unwrap().value);
+    }
+}
@@ -0,0 +1,235 @@
+use crate::{
+    config::Fields,
+    field::{Field, FieldMap},
+    reader::{ReadError, Reader},
+    source::{Source, SourceType},
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde_json::Value;
+
+// Reads the output of `journalctl -o export` and `journalctl -o json`.
+//
+// The export format is a list of `NAME=value` lines, records separated by a blank line.
+// Fields that aren't valid text are written as the field name on its own line,
+// followed by a little-endian 64-bit length, the data and a newline.
+pub struct JournalReader {
+    source: Source,
+    fields: Fields,
+    record: FieldMap,
+    binary: Option<Binary>,
+}
+
+// Binary fields longer than this are taken as corrupt input rather than allocated.
+const MAX_BINARY_LEN: u64 = 64 * 1024 * 1024;
+
+// A binary field whose data wasn't completely available yet.
+struct Binary {
+    name: String,
+    len: Option<u64>,
+}
+
+impl JournalReader {
+    pub fn new(source: Source, fields: Fields) -> Self {
+        Self {
+            source,
+            fields,
+            record: FieldMap::new(),
+            binary: None,
+        }
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        self.record.insert(name.clone(), Field::new(name, value));
+    }
+
+    // Returns false if the data isn't completely available yet.
+    async fn read_binary(&mut self, mut binary: Binary) -> Result<bool, ReadError> {
+        let len = match binary.len {
+            Some(len) => len,
+            None => match self.source.read_exact(8).await {
+                Some(bytes) => u64::from_le_bytes(bytes.try_into().unwrap()),
+                None => {
+                    self.binary = Some(binary);
+                    return Ok(false);
+                }
+            },
+        };
+        // The data is followed by a newline.
+        let size = len
+            .checked_add(1)
+            .filter(|_| len <= MAX_BINARY_LEN)
+            .and_then(|size| usize::try_from(size).ok());
+        let Some(size) = size else {
+            // The rest of the record can't be trusted either.
+            self.record.clear();
+            return Err(ReadError::ParseFail(anyhow!(
+                "binary field `{}` claims {} bytes, more than the limit of {}",
+                binary.name,
+                len,
+                MAX_BINARY_LEN
+            )));
+        };
+
+        match self.source.read_exact(size).await {
+            Some(mut data) => {
+                data.pop();
+                let value = String::from_utf8_lossy(&data).into_owned();
+                self.insert(binary.name, value);
+                Ok(true)
+            }
+            None => {
+                binary.len = Some(len);
+                self.binary = Some(binary);
+                Ok(false)
+            }
+        }
+    }
+}
+
+fn parse_json(line: &str) -> Result<FieldMap, ReadError> {
+    let json: Value =
+        serde_json::from_str(line).map_err(|e| ReadError::ParseFail(anyhow::Error::new(e)))?;
+    let json = json.as_object().ok_or(ReadError::ParseFail(anyhow!(
+        "journal entry is not an object"
+    )))?;
+
+    let mut map = FieldMap::with_capacity(json.len());
+    for (name, value) in json {
+        let value = match value {
+            Value::String(s) => s.clone(),
+            // Binary fields are arrays of bytes.
+            Value::Array(array) if array.iter().all(|v| v.is_u64()) => {
+                let bytes: Vec<u8> = array
+                    .iter()
+                    .filter_map(|v| v.as_u64())
+                    .map(|v| v as u8)
+                    .collect();
+                String::from_utf8_lossy(&bytes).into_owned()
+            }
+            _ => value.to_string(),
+        };
+        map.insert(name.clone(), Field::new(name.clone(), value));
+    }
+    Ok(map)
+}
+
+#[async_trait]
+impl Reader for JournalReader {
+    fn source_type(&self) -> SourceType {
+        self.source.source_type()
+    }
+
+    fn format_fields(&self) -> &Fields {
+        &self.fields
+    }
+
+    fn take_raw(&mut self) -> String {
+        self.source.take_raw()
+    }
+
+    fn into_source(self: Box<Self>) -> Source {
+        self.source
+    }
+
+    async fn read_fields(&mut self) -> Result<FieldMap, ReadError> {
+        loop {
+            if let Some(binary) = self.binary.take() {
+                if !self.read_binary(binary).await? {
+                    return Err(ReadError::Eof);
+                }
+            }
+
+            // A record that isn't finished yet is kept until more input arrives.
+            let mut line = match self.source.read_line_bytes().await {
+                Some(line) => line,
+                None => return Err(ReadError::Eof),
+            };
+            if line.last() == Some(&b'\n') {
+                line.pop();
+            }
+
+            if line.is_empty() {
+                if self.record.is_empty() {
+                    continue;
+                }
+                return Ok(std::mem::take(&mut self.record));
+            }
+
+            let line = String::from_utf8_lossy(&line);
+
+            // `journalctl -o json` writes one object per line.
+            if self.record.is_empty() && line.starts_with('{') {
+                return parse_json(&line);
+            }
+
+            match line.split_once('=') {
+                Some((name, value)) => self.insert(name.to_string(), value.to_string()),
+                None => {
+                    self.binary = Some(Binary {
+                        name: line.into_owned(),
+                        len: None,
+                    })
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use tokio::io::BufReader;
+
+    use crate::{reader::Reader, source::Source};
+
+    use super::JournalReader;
+
+    #[tokio::test]
+    async fn journal_reader_export() {
+        let mut input =
+            b"__REALTIME_TIMESTAMP=1700000000000000\nPRIORITY=6\nMESSAGE=first\n\n".to_vec();
+        input.extend_from_slice(b"PRIORITY=3\nMESSAGE\n");
+        input.extend_from_slice(&11u64.to_le_bytes());
+        input.extend_from_slice(b"two\nlines\x00!\n\n");
+        input.extend_from_slice(b"{\"MESSAGE\":[104,105],\"PRIORITY\":\"4\"}\n");
+
+        let source = BufReader::new(Cursor::new(input));
+        let source = Source::new(crate::source::SourceType::File("test".to_string()), source);
+        let mut reader = JournalReader::new(source, Default::default());
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("first", fields.get("MESSAGE").unwrap().value);
+        assert_eq!("6", fields.get("PRIORITY").unwrap().value);
+        assert_eq!(
+            "1700000000000000",
+            fields.get("__REALTIME_TIMESTAMP").unwrap().value
+        );
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("two\nlines\0!", fields.get("MESSAGE").unwrap().value);
+        assert_eq!("3", fields.get("PRIORITY").unwrap().value);
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("hi", fields.get("MESSAGE").unwrap().value);
+        assert_eq!("4", fields.get("PRIORITY").unwrap().value);
+    }
+
+    #[tokio::test]
+    async fn journal_reader_bad_length() {
+        for len in [u64::MAX, 0x100000000000] {
+            let mut input = b"MESSAGE\n".to_vec();
+            input.extend_from_slice(&len.to_le_bytes());
+            input.extend_from_slice(b"\nMESSAGE=next\n\n");
+
+            let source = BufReader::new(Cursor::new(input));
+            let source = Source::new(crate::source::SourceType::File("test".to_string()), source);
+            let mut reader = JournalReader::new(source, Default::default());
+
+            assert!(reader.read_fields().await.is_err());
+            reader.take_raw();
+            let fields = reader.read_fields().await.unwrap();
+            assert_eq!("next", fields.get("MESSAGE").unwrap().value);
+        }
+    }
+}
@@ -1,96 +1,265 @@
 use crate::{
-    config::{Exclude, Include},
-    field::Field,
+    config::{Fields, Nested},
+    field::{Field, FieldMap, Kind},
+    json_path::{self, JsonPath},
     reader::{ReadError, Reader},
     source::{Source, SourceType},
 };
 use anyhow::anyhow;
 use async_trait::async_trait;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::sync::Arc;
 
 pub struct JsonReader {
     source: Source,
-    exclude_all: bool,
-    exclude: Vec<String>,
-    include: Vec<String>,
+    fields: Fields,
+    parser: Arc<JsonParser>,
 }
 
 impl JsonReader {
-    pub fn new(source: Source, exclude: Exclude, include: Include) -> Self {
-        let mut exclude_all = false;
-
-        let exclude = match exclude {
-            Exclude::ExcludeOne(one) => {
-                if one == "all" {
-                    exclude_all = true;
-                    vec![]
-                } else {
-                    vec![one]
+    // `fields` are the format's fields as returned by `normalize_fields`. The parser is shared
+    // by every reader of the format.
+    pub fn new(source: Source, fields: Fields, parser: Arc<JsonParser>) -> Self {
+        Self {
+            source,
+            fields,
+            parser,
+        }
+    }
+}
+
+// Replaces path selectors with the names of the fields they produce,
+// e.g. `$.log.msg` with `log.msg`.
+pub fn normalize_fields(fields: &Fields) -> anyhow::Result<Fields> {
+    let normalize = |name: &Option<String>| -> anyhow::Result<Option<String>> {
+        name.as_deref()
+            .map(|name| JsonPath::parse(name).map(|path| path.to_string()))
+            .transpose()
+    };
+
+    let mut normalized = fields.clone();
+    normalized.message = normalize(&fields.message)?;
+    normalized.level = normalize(&fields.level)?;
+    normalized.stacktrace = normalize(&fields.stacktrace)?;
+    if let Some(timestamp) = &mut normalized.timestamp {
+        timestamp.name = JsonPath::parse(&timestamp.name)?.to_string();
+    }
+    Ok(normalized)
+}
+
+// Turns a single JSON object into fields. Shared by `JsonReader` and by formats
+// that embed JSON inside a line (see `config::Payload`).
+pub struct JsonParser {
+    nested: Nested,
+    exclude_all: bool,
+    exclude: Vec<JsonPath>,
+    include: Vec<JsonPath>,
+}
+
+impl JsonParser {
+    pub fn new(fields: Fields) -> anyhow::Result<Self> {
+        let (exclude_all, exclude) = fields.excluded();
+        Ok(Self {
+            nested: fields.nested,
+            exclude_all,
+            exclude: parse_paths(exclude)?,
+            include: parse_paths(fields.included())?,
+        })
+    }
+
+    pub fn parse(&self, line: &str) -> Result<FieldMap, ReadError> {
+        let line = line.trim();
+        match line.chars().next() {
+            Some(c) => {
+                if c != '{' {
+                    return Err(ReadError::ParseFail(anyhow!(
+                        "first character was not '{{'"
+                    )));
                 }
             }
-            Exclude::ExcludeMany(many) => many,
-        };
+            None => {
+                return Err(ReadError::ParseFail(anyhow!(
+                    "couldn't access the first character. input is probably empty"
+                )))
+            }
+        }
 
-        let include = match include {
-            Include::IncludeOne(one) => vec![one],
-            Include::IncludeMany(many) => many,
+        let json: Value =
+            serde_json::from_str(line).map_err(|e| ReadError::ParseFail(anyhow::Error::new(e)))?;
+        if !json.is_object() {
+            return Err(ReadError::Internal(anyhow!("json.as_object failed")));
+        }
+
+        Ok(self.parse_object(&json))
+    }
+
+    // Turns an already parsed JSON object into fields.
+    pub fn parse_object(&self, json: &Value) -> FieldMap {
+        let mut map = FieldMap::new();
+
+        if !self.exclude_all {
+            let mut remaining = json.clone();
+            for path in &self.exclude {
+                path.remove(&mut remaining);
+            }
+            // A nested value selected by `include` is a field of its own, so it's taken out of
+            // the parent kept around it rather than shown twice. A parent left empty is dropped.
+            for path in &self.include {
+                let Some(key) = path.parent_key() else {
+                    continue;
+                };
+                if path.remove(&mut remaining) && is_empty(&remaining[key]) {
+                    remaining.as_object_mut().unwrap().remove(key);
+                }
+            }
+            if let Value::Object(remaining) = remaining {
+                for (k, v) in &remaining {
+                    self.insert(&mut map, k.clone(), v);
+                }
+            }
+        }
+
+        for path in &self.include {
+            if let Some(v) = path.select(json) {
+                self.insert(&mut map, path.to_string(), v);
+            }
+        }
+
+        map
+    }
+
+    fn insert(&self, map: &mut FieldMap, name: String, value: &Value) {
+        if self.nested == Nested::Flatten {
+            json_path::flatten(&name, value, &mut |name, value| {
+                map.insert(name.clone(), self.to_field(name, value));
+            });
+        } else {
+            map.insert(name.clone(), self.to_field(name, value));
+        }
+    }
+
+    fn to_field(&self, name: String, value: &Value) -> Field {
+        let kind = match value {
+            Value::String(_) => Kind::String,
+            Value::Number(_) => Kind::Number,
+            Value::Bool(_) => Kind::Bool,
+            Value::Null => Kind::Null,
+            Value::Object(_) | Value::Array(_) => Kind::Json,
         };
+        Field::typed(name, self.value_to_string(value), kind)
+    }
 
-        Self {
-            source: source,
-            exclude_all: exclude_all,
-            exclude: exclude,
-            include: include,
+    fn value_to_string(&self, value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            Value::Object(_) | Value::Array(_) if self.nested == Nested::Pretty => {
+                serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+            }
+            _ => value.to_string(),
         }
     }
 }
 
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => map.is_empty(),
+        Value::Array(array) => array.is_empty(),
+        _ => false,
+    }
+}
+
+fn parse_paths(paths: Vec<String>) -> anyhow::Result<Vec<JsonPath>> {
+    paths.iter().map(|path| JsonPath::parse(path)).collect()
+}
+
 #[async_trait]
 impl Reader for JsonReader {
     fn source_type(&self) -> SourceType {
         self.source.source_type()
     }
 
-    async fn read_fields(&mut self) -> Result<HashMap<String, Field>, ReadError> {
+    fn format_fields(&self) -> &Fields {
+        &self.fields
+    }
+
+    fn take_raw(&mut self) -> String {
+        self.source.take_raw()
+    }
+
+    fn into_source(self: Box<Self>) -> Source {
+        self.source
+    }
+
+    async fn read_fields(&mut self) -> Result<FieldMap, ReadError> {
         let line = self.source.read_line().await;
         if let Some(line) = line {
-            let line = line.trim();
-            match line.chars().nth(0) {
-                Some(c) => {
-                    if c != '{' {
-                        println!("line: {}", line);
-                        return Err(ReadError::ParseFail(anyhow!(
-                            "first character was not '{{'"
-                        )));
-                    }
-                }
-                None => {
-                    return Err(ReadError::ParseFail(anyhow!(
-                        "couldn't access the first character. input is probably empty"
-                    )))
-                }
-            }
-
-            let json: Value = serde_json::from_str(line)
-                .map_err(|e| ReadError::ParseFail(anyhow::Error::new(e)))?;
-            let json_map = json
-                .as_object()
-                .ok_or(ReadError::Internal(anyhow!("json.as_object failed")))?;
-
-            let mut map = HashMap::with_capacity(json_map.len());
-
-            for (k, v) in json_map {
-                map.insert(
-                    k.clone(),
-                    Field {
-                        name: k.clone(),
-                        value: v.to_string(),
-                    },
-                );
-            }
-            return Ok(map);
+            return self.parser.parse(&line);
         }
         Err(ReadError::Eof)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+    use tokio::io::BufReader;
+
+    use crate::{
+        config::{Exclude, Fields, Nested, TimestampField},
+        reader::Reader,
+        source::Source,
+    };
+
+    use super::{normalize_fields, JsonParser, JsonReader};
+
+    #[tokio::test]
+    async fn json_reader_nested() {
+        let c = Cursor::new(
+            r#"{"ts":1,"log":{"msg":"hi"},"http":{"status":500,"headers":{"host":"a"}},"tags":["x","y"]}"#,
+        );
+
+        let source = BufReader::new(c);
+        let source = Source::new(crate::source::SourceType::File("test".to_string()), source);
+
+        let fields = Fields {
+            message: Some("$.log.msg".to_string()),
+            timestamp: Some(TimestampField {
+                name: "ts".to_string(),
+                format: "unix".to_string(),
+            }),
+            stacktrace: None,
+            exclude: Some(Exclude::ExcludeMany(vec!["http.headers".to_string()])),
+            nested: Nested::Flatten,
+            ..Default::default()
+        };
+        let parser = Arc::new(JsonParser::new(fields.clone()).unwrap());
+        let mut reader = JsonReader::new(source, normalize_fields(&fields).unwrap(), parser);
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("hi", fields.get("log.msg").unwrap().value);
+        assert_eq!("500", fields.get("http.status").unwrap().value);
+        assert_eq!("y", fields.get("tags[1]").unwrap().value);
+        assert_eq!("1", fields.get("ts").unwrap().value);
+        assert!(!fields.contains_key("http.headers.host"));
+        assert!(!fields.contains_key("http"));
+    }
+
+    #[test]
+    fn json_parser_selected_once() {
+        let fields = Fields {
+            message: Some("$.log.msg".to_string()),
+            level: Some("$.meta.level".to_string()),
+            ..Default::default()
+        };
+        let parser = JsonParser::new(fields).unwrap();
+        let fields = parser
+            .parse(r#"{"log":{"msg":"hi","x":1},"meta":{"level":"info"},"a":"b"}"#)
+            .unwrap();
+
+        assert_eq!("hi", fields["log.msg"].value);
+        assert_eq!(r#"{"x":1}"#, fields["log"].value);
+        assert_eq!("info", fields["meta.level"].value);
+        assert!(!fields.contains_key("meta"));
+        assert_eq!("b", fields["a"].value);
+    }
+}
@@ -1,33 +1,78 @@
-use std::collections::HashMap;
-
 use crate::{
-    field::Field,
+    config::Fields,
+    field::{Field, FieldMap},
+    logfmt,
     reader::{ReadError, Reader},
+    reader_json::JsonParser,
     source::{Source, SourceType},
 };
 use anyhow::anyhow;
 use async_trait::async_trait;
 use regex::Regex;
+use serde_json::Value;
+use std::sync::Arc;
 
 pub struct RegexReader {
     re: Regex,
     capture_names: Vec<String>,
     source: Source,
+    fields: Fields,
+    payload: Option<(String, Arc<PayloadParser>)>,
+}
+
+// Parses the value of a named capture into more fields. Both types go through a
+// `JsonParser`, so the format's `exclude`, `include` and `nested` apply to either.
+pub enum PayloadParser {
+    Json(JsonParser),
+    Logfmt(JsonParser),
+}
+
+impl PayloadParser {
+    fn parse(&self, s: &str) -> Result<FieldMap, ReadError> {
+        match self {
+            Self::Json(parser) => parser.parse(s),
+            Self::Logfmt(parser) => {
+                let pairs = logfmt::parse(s).map_err(ReadError::ParseFail)?;
+                // A value holding a JSON object or array is a nested value, anything else
+                // is text.
+                let object = pairs
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let value = match serde_json::from_str::<Value>(&value) {
+                            Ok(json) if json.is_object() || json.is_array() => json,
+                            _ => Value::String(value),
+                        };
+                        (name, value)
+                    })
+                    .collect();
+                Ok(parser.parse_object(&Value::Object(object)))
+            }
+        }
+    }
 }
 
 impl RegexReader {
-    pub fn new(source: Source, re: Regex) -> Self {
+    pub fn new(source: Source, re: Regex, fields: Fields) -> Self {
         let capture_names = re
             .capture_names()
             .filter_map(|v| v.map(|x| x.to_string()))
             .collect();
 
         Self {
-            re: re,
-            capture_names: capture_names,
-            source: source,
+            re,
+            capture_names,
+            source,
+            fields,
+            payload: None,
         }
     }
+
+    // The capture named `capture` is parsed with `parser` and replaced by the resulting fields.
+    // If parsing fails, the capture is kept as a plain field.
+    pub fn with_payload(mut self, capture: String, parser: Arc<PayloadParser>) -> Self {
+        self.payload = Some((capture, parser));
+        self
+    }
 }
 
 #[async_trait]
@@ -36,11 +81,23 @@ impl Reader for RegexReader {
         self.source.source_type()
     }
 
-    async fn read_fields(&mut self) -> Result<HashMap<String, Field>, ReadError> {
+    fn format_fields(&self) -> &Fields {
+        &self.fields
+    }
+
+    fn take_raw(&mut self) -> String {
+        self.source.take_raw()
+    }
+
+    fn into_source(self: Box<Self>) -> Source {
+        self.source
+    }
+
+    async fn read_fields(&mut self) -> Result<FieldMap, ReadError> {
         let line = self.source.read_line().await;
         if let Some(line) = line {
             let line = line.trim();
-            let mut map = HashMap::with_capacity(self.capture_names.len());
+            let mut map = FieldMap::with_capacity(self.capture_names.len());
 
             #[cfg(test)]
             println!("regex: line: `{line}`");
@@ -48,25 +105,35 @@ impl Reader for RegexReader {
             // TODO: Improve error?
             let caps = self
                 .re
-                .captures(&line)
+                .captures(line)
                 .ok_or(ReadError::ParseFail(anyhow!("regex doesn't match")))?;
 
             for name in &self.capture_names {
                 match caps.name(name) {
                     Some(cap) => {
                         let cap = cap.as_str();
-                        map.insert(
-                            name.clone(),
-                            Field {
-                                name: name.clone(),
-                                value: cap.to_string(),
-                            },
-                        );
+                        map.insert(name.clone(), Field::new(name.clone(), cap.to_string()));
                     }
                     None => continue,
                 }
             }
 
+            if let Some((capture, parser)) = &self.payload {
+                let parsed = map.get(capture).map(|field| parser.parse(&field.value));
+                if let Some(Ok(payload_fields)) = parsed {
+                    // Parsed fields take the place of the capture. A parsed field named like
+                    // another capture doesn't replace it, it's renamed to `<capture>.<name>`.
+                    let (index, _, _) = map.shift_remove_full(capture).unwrap();
+                    for (i, (mut name, mut field)) in payload_fields.into_iter().enumerate() {
+                        while map.contains_key(&name) {
+                            name = format!("{}.{}", capture, name);
+                        }
+                        field.name.clone_from(&name);
+                        map.shift_insert((index + i).min(map.len()), name, field);
+                    }
+                }
+            }
+
             return Ok(map);
         }
         Err(ReadError::Eof)
@@ -77,11 +144,16 @@ impl Reader for RegexReader {
 mod tests {
     use regex::Regex;
     use std::io::Cursor;
-    use tokio::io::{AsyncRead, AsyncSeek, BufReader};
+    use tokio::io::BufReader;
 
-    use crate::{reader::Reader, source::Source};
+    use crate::{
+        config::{Exclude, Fields, Nested},
+        reader::Reader,
+        reader_json::JsonParser,
+        source::Source,
+    };
 
-    use super::RegexReader;
+    use super::{PayloadParser, RegexReader};
 
     #[tokio::test]
     async fn regex_reader() {
@@ -92,7 +164,7 @@ mod tests {
 
         // first_name + optional space + optional last_name
         let re = Regex::new(r#"^(?P<first_name>[a-zA-Z]+)[ ]?(?P<last_name>[a-zA-Z]+)?"#).unwrap();
-        let mut reader = RegexReader::new(re, source);
+        let mut reader = RegexReader::new(source, re, Default::default());
 
         let fields = reader.read_fields().await.unwrap();
         let first_name = fields.get("first_name").unwrap();
@@ -106,6 +178,70 @@ mod tests {
         let first_name = fields.get("first_name").unwrap();
         assert_eq!("first_name", first_name.name);
         assert_eq!("Alice", first_name.value);
-        assert_eq!(true, fields.get("last_name").is_none());
+        assert!(!fields.contains_key("last_name"));
+    }
+
+    #[tokio::test]
+    async fn regex_reader_payload() {
+        let c = Cursor::new(
+            "2024-01-01T00:00:00Z stdout F {\"msg\":\"hi\",\"level\":\"info\"}\n2024-01-01T00:00:01Z stderr F plain text",
+        );
+
+        let source = BufReader::new(c);
+        let source = Source::new(crate::source::SourceType::File("test".to_string()), source);
+
+        let re =
+            Regex::new(r#"^(?P<time>\S+) (?P<stream>\S+) (?P<tag>[PF]) (?P<log>.*)$"#).unwrap();
+        let parser = PayloadParser::Json(JsonParser::new(Default::default()).unwrap());
+        let parser = std::sync::Arc::new(parser);
+        let mut reader = RegexReader::new(source, re, Default::default())
+            .with_payload("log".to_string(), parser);
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("stdout", fields.get("stream").unwrap().value);
+        assert_eq!("hi", fields.get("msg").unwrap().value);
+        assert_eq!("info", fields.get("level").unwrap().value);
+        assert!(!fields.contains_key("log"));
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("stderr", fields.get("stream").unwrap().value);
+        assert_eq!("plain text", fields.get("log").unwrap().value);
+    }
+
+    #[tokio::test]
+    async fn regex_reader_logfmt_payload() {
+        let c = Cursor::new(
+            "stdout level=info msg=\"hi there\" stream=other secret=x http={\"status\":200}\nstderr oops=\"unterminated",
+        );
+
+        let source = BufReader::new(c);
+        let source = Source::new(crate::source::SourceType::File("test".to_string()), source);
+
+        let re = Regex::new(r#"^(?P<stream>\S+) (?P<log>.*)$"#).unwrap();
+        let fields = Fields {
+            exclude: Some(Exclude::ExcludeMany(vec!["secret".to_string()])),
+            nested: Nested::Flatten,
+            ..Default::default()
+        };
+        let parser = PayloadParser::Logfmt(JsonParser::new(fields.clone()).unwrap());
+        let parser = std::sync::Arc::new(parser);
+        let mut reader =
+            RegexReader::new(source, re, fields).with_payload("log".to_string(), parser);
+
+        // The payload's `stream` doesn't replace the capture.
+        let fields = reader.read_fields().await.unwrap();
+        let names: Vec<&str> = fields.keys().map(|k| k.as_str()).collect();
+        assert_eq!(
+            vec!["stream", "level", "msg", "log.stream", "http.status"],
+            names
+        );
+        assert_eq!("stdout", fields.get("stream").unwrap().value);
+        assert_eq!("other", fields.get("log.stream").unwrap().value);
+        assert_eq!("log.stream", fields.get("log.stream").unwrap().name);
+        assert_eq!("hi there", fields.get("msg").unwrap().value);
+        assert_eq!("200", fields.get("http.status").unwrap().value);
+
+        let fields = reader.read_fields().await.unwrap();
+        assert_eq!("oops=\"unterminated", fields.get("log").unwrap().value);
     }
 }
@@ -1,6 +1,8 @@
-use std::io::SeekFrom;
+use std::{fmt::Display, io::SeekFrom};
 
-use tokio::io::{self, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncSeek, AsyncSeekExt};
+use tokio::io::{
+    self, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt,
+};
 
 pub trait AsyncReadSeek: AsyncBufRead + AsyncRead + AsyncSeek + Unpin + Send + Sync {}
 
@@ -12,10 +14,48 @@ pub enum SourceType {
     File(String),
 }
 
+impl Display for SourceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stdin => write!(f, "stdin"),
+            Self::File(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+// How the bytes of a source are turned into text.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Encoding {
+    // Invalid bytes end the source.
+    #[default]
+    Utf8,
+    // ISO-8859-1, every byte is a character.
+    Latin1,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> anyhow::Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf8" | "utf-8" => Ok(Self::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Self::Latin1),
+            _ => anyhow::bail!("unknown encoding `{}`, use utf8 or latin1", name),
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Self::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+        }
+    }
+}
+
 pub struct Source {
     source_type: SourceType,
     source: Box<dyn AsyncReadSeek>,
+    encoding: Encoding,
     position: usize,
+    raw: String,
 }
 
 impl Source {
@@ -25,33 +65,92 @@ impl Source {
         T: AsyncReadSeek + 'static,
     {
         Self {
-            source_type: source_type,
+            source_type,
             source: Box::new(source),
+            encoding: Encoding::Utf8,
             position: 0,
+            raw: String::new(),
         }
     }
 
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
     pub fn source_type(&self) -> SourceType {
         self.source_type.clone()
     }
 
-    pub async fn read_line(&mut self) -> Option<String> {
-        let res = self
-            .source
+    // Seeking a BufReader drops its buffer. Stdin can't seek back, so that input would be lost.
+    async fn seek_to_position(&mut self) -> bool {
+        if self.source_type == SourceType::Stdin {
+            return true;
+        }
+        self.source
             .seek(SeekFrom::Start(self.position as u64))
-            .await;
-        if res.is_err() {
+            .await
+            .is_ok()
+    }
+
+    pub async fn read_line(&mut self) -> Option<String> {
+        if !self.seek_to_position().await {
             return None;
         }
 
         let mut buf = String::new();
-        let size = self.source.read_line(&mut buf).await.unwrap_or(0);
+        let size = match self.encoding {
+            Encoding::Utf8 => self.source.read_line(&mut buf).await.unwrap_or(0),
+            Encoding::Latin1 => {
+                let mut bytes = Vec::new();
+                let size = self.source.read_until(b'\n', &mut bytes).await.unwrap_or(0);
+                buf = self.encoding.decode(&bytes);
+                size
+            }
+        };
         if size == 0 {
             return None;
         }
         self.position += size;
+        self.raw.push_str(&buf);
         Some(buf)
     }
+
+    // Like `read_line`, but doesn't require the line to be valid UTF-8.
+    pub async fn read_line_bytes(&mut self) -> Option<Vec<u8>> {
+        if !self.seek_to_position().await {
+            return None;
+        }
+
+        let mut buf = Vec::new();
+        let size = self.source.read_until(b'\n', &mut buf).await.unwrap_or(0);
+        if size == 0 {
+            return None;
+        }
+        self.position += size;
+        self.raw.push_str(&self.encoding.decode(&buf));
+        Some(buf)
+    }
+
+    // Reads exactly `len` bytes. If a file doesn't have enough bytes yet, the next read starts
+    // over from the same position. Stdin can't seek back, but it waits for the bytes, so it's
+    // only short of them at its end, and those are lost.
+    pub async fn read_exact(&mut self, len: usize) -> Option<Vec<u8>> {
+        if !self.seek_to_position().await {
+            return None;
+        }
+
+        let mut buf = vec![0; len];
+        self.source.read_exact(&mut buf).await.ok()?;
+        self.position += len;
+        self.raw.push_str(&self.encoding.decode(&buf));
+        Some(buf)
+    }
+
+    // Returns the input read since the last call, i.e. the raw text of the last record.
+    pub fn take_raw(&mut self) -> String {
+        std::mem::take(&mut self.raw)
+    }
 }
 
 // A wrapper for io::Stdin that additionaly provides AsyncSeek trait.
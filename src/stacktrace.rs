@@ -0,0 +1,391 @@
+use crate::{
+    config::Stacktrace,
+    field::{Field, Kind},
+    theme::Theme,
+};
+use regex::Regex;
+use std::sync::OnceLock;
+
+const INDENT: &str = "    ";
+
+// Functions and files of standard and widely used third party libraries. Used to tell app
+// frames from library frames when `app_packages` isn't set.
+const LIBRARY_FUNCTIONS: [&str; 21] = [
+    "java.",
+    "javax.",
+    "jdk.",
+    "sun.",
+    "com.sun.",
+    "kotlin.",
+    "kotlinx.",
+    "scala.",
+    "org.springframework.",
+    "org.apache.",
+    "io.netty.",
+    "runtime.",
+    "runtime/",
+    "net/http.",
+    "std::",
+    "core::",
+    "alloc::",
+    "tokio::",
+    "<std::",
+    "<core::",
+    "<alloc::",
+];
+const LIBRARY_LOCATIONS: [&str; 8] = [
+    "site-packages/",
+    "dist-packages/",
+    "/lib/python",
+    "<frozen ",
+    "/usr/local/go/src/",
+    "/go/pkg/mod/",
+    "/rustc/",
+    "/.cargo/registry/",
+];
+
+// A line of a stack trace.
+#[derive(Debug, PartialEq)]
+pub enum Line {
+    // Anything that isn't a frame, e.g. `java.lang.IllegalStateException: boom`,
+    // `Caused by: ...`, `Traceback (most recent call last):` or `goroutine 1 [running]:`.
+    Text(String),
+    Frame(Frame),
+    // Frames left out by the runtime, e.g. Java's `... 12 more`.
+    Omitted(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    // File and line, e.g. `Main.java:42` or `/app/main.go:12`.
+    pub location: Option<String>,
+    // The source line Python shows below a frame.
+    pub code: Option<String>,
+}
+
+struct Patterns {
+    java: Regex,
+    python: Regex,
+    go_location: Regex,
+    rust_frame: Regex,
+    rust_location: Regex,
+    omitted: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        // `	at com.example.Main.run(Main.java:42)`
+        java: Regex::new(r"^\s*at (?P<function>[^\s(]+)\((?P<location>[^)]*)\)\s*$").unwrap(),
+        // `  File "/app/main.py", line 10, in handler`
+        python: Regex::new(
+            r#"^\s*File "(?P<file>[^"]+)", line (?P<line>\d+)(?:, in (?P<function>.+))?$"#,
+        )
+        .unwrap(),
+        // `	/app/main.go:12 +0x1d`, below the function of the frame.
+        go_location: Regex::new(r"^\s+(?P<location>\S+\.go:\d+)(?: \+0x[0-9a-f]+)?$").unwrap(),
+        // `   4: myservice::handler` or `   4: 0x5581 - myservice::handler`
+        rust_frame: Regex::new(r"^\s*\d+:\s+(?:0x[0-9a-f]+ - )?(?P<function>\S.*)$").unwrap(),
+        // `             at ./src/main.rs:10:5`, below the function of the frame.
+        rust_location: Regex::new(r"^\s+at (?P<location>\S.*)$").unwrap(),
+        omitted: Regex::new(r"^\s*\.\.\. \d+ (?:more|common frames omitted)").unwrap(),
+    })
+}
+
+// The text of a stack trace field. Traces logged as an array of lines are joined.
+pub fn text(field: &Field) -> String {
+    if field.kind == Kind::Json {
+        if let Ok(serde_json::Value::Array(lines)) = serde_json::from_str(&field.value) {
+            return lines
+                .iter()
+                .map(|line| match line {
+                    serde_json::Value::String(line) => line.clone(),
+                    line => line.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+    }
+    field.value.clone()
+}
+
+// Splits a Java, Python, Go panic or Rust backtrace into lines and frames.
+// Anything that isn't recognized is kept as text.
+pub fn parse(trace: &str) -> Vec<Line> {
+    let patterns = patterns();
+    let mut parsed = Vec::new();
+    let mut lines = trace.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(captures) = patterns.java.captures(line) {
+            parsed.push(Line::Frame(Frame {
+                function: captures["function"].to_string(),
+                location: Some(captures["location"].to_string()),
+                code: None,
+            }));
+            continue;
+        }
+
+        if let Some(captures) = patterns.python.captures(line) {
+            let function = captures.name("function").map_or("<module>", |m| m.as_str());
+            let mut frame = Frame {
+                function: function.to_string(),
+                location: Some(format!("{}:{}", &captures["file"], &captures["line"])),
+                code: None,
+            };
+            // The source line, if there's one.
+            if let Some(next) = lines.peek() {
+                if next.starts_with(char::is_whitespace) && !patterns.python.is_match(next) {
+                    frame.code = Some(next.trim().to_string());
+                    lines.next();
+                }
+            }
+            parsed.push(Line::Frame(frame));
+            continue;
+        }
+
+        // Go: the function, then its location on the next line.
+        if let Some(captures) = lines
+            .peek()
+            .and_then(|next| patterns.go_location.captures(next))
+        {
+            parsed.push(Line::Frame(Frame {
+                function: line.trim().to_string(),
+                location: Some(captures["location"].to_string()),
+                code: None,
+            }));
+            lines.next();
+            continue;
+        }
+
+        if let Some(captures) = patterns.rust_frame.captures(line) {
+            let mut frame = Frame {
+                function: captures["function"].to_string(),
+                location: None,
+                code: None,
+            };
+            if let Some(captures) = lines
+                .peek()
+                .and_then(|next| patterns.rust_location.captures(next))
+            {
+                frame.location = Some(captures["location"].to_string());
+                lines.next();
+            }
+            parsed.push(Line::Frame(frame));
+            continue;
+        }
+
+        if patterns.omitted.is_match(line) {
+            parsed.push(Line::Omitted(line.trim().to_string()));
+            continue;
+        }
+
+        parsed.push(Line::Text(line.trim().to_string()));
+    }
+
+    parsed
+}
+
+fn is_app_frame(frame: &Frame, options: &Stacktrace) -> bool {
+    let location = frame.location.as_deref().unwrap_or("");
+    if !options.app_packages.is_empty() {
+        return options
+            .app_packages
+            .iter()
+            .any(|package| frame.function.starts_with(package) || location.starts_with(package));
+    }
+
+    // Java frames can start with a module, e.g. `java.base/java.lang.Thread.run`.
+    let function = match frame.function.split_once('/') {
+        Some((module, function)) if module.contains('.') => function,
+        _ => frame.function.as_str(),
+    };
+    let library = LIBRARY_FUNCTIONS
+        .iter()
+        .any(|prefix| function.starts_with(prefix))
+        || LIBRARY_LOCATIONS
+            .iter()
+            .any(|pattern| location.contains(pattern));
+    !library
+}
+
+// Renders a stack trace on indented lines, to be shown below its record.
+// Colors are only used if there's a `theme`.
+pub fn render(trace: &str, options: &Stacktrace, theme: Option<&Theme>) -> Vec<String> {
+    let paint = |key: &str, s: &str| match theme {
+        Some(theme) => theme.paint(key, s),
+        None => s.to_string(),
+    };
+
+    let lines = parse(trace);
+    let frames: Vec<Option<bool>> = lines
+        .iter()
+        .map(|line| match line {
+            Line::Frame(frame) => Some(is_app_frame(frame, options)),
+            _ => None,
+        })
+        .collect();
+    // Folding library frames only makes sense if there's something else to look at.
+    let has_app_frames = frames.contains(&Some(true));
+
+    let mut rendered = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let frame = match &lines[i] {
+            Line::Text(text) => {
+                rendered.push(format!("{}{}", INDENT, paint("stacktrace.text", text)));
+                i += 1;
+                continue;
+            }
+            Line::Omitted(text) => {
+                rendered.push(format!("{}{}", INDENT, paint("stacktrace.library", text)));
+                i += 1;
+                continue;
+            }
+            Line::Frame(frame) => frame,
+        };
+        let app = frames[i] == Some(true);
+
+        if options.collapse && has_app_frames && !app {
+            let run = frames[i..]
+                .iter()
+                .take_while(|app| **app == Some(false))
+                .count();
+            if run > 1 {
+                let folded = format!("... {} library frames", run);
+                rendered.push(format!(
+                    "{}{}",
+                    INDENT,
+                    paint("stacktrace.library", &folded)
+                ));
+                i += run;
+                continue;
+            }
+        }
+
+        let style = if app {
+            "stacktrace.app"
+        } else {
+            "stacktrace.library"
+        };
+        let mut line = format!(
+            "{}{}",
+            INDENT,
+            paint(style, &format!("at {}", frame.function))
+        );
+        if let Some(location) = &frame.location {
+            let location = format!(" ({})", location);
+            line.push_str(&if app {
+                location
+            } else {
+                paint("stacktrace.library", &location)
+            });
+        }
+        rendered.push(line);
+        if let Some(code) = &frame.code {
+            rendered.push(format!(
+                "{}{}{}",
+                INDENT,
+                INDENT,
+                paint("stacktrace.library", code)
+            ));
+        }
+        i += 1;
+
+        // Recursion shows up as the same frame over and over.
+        if options.collapse {
+            let repeated = lines[i..]
+                .iter()
+                .take_while(|line| matches!(line, Line::Frame(next) if next == frame))
+                .count();
+            if repeated > 0 {
+                let folded = format!("... same frame {} more times", repeated);
+                rendered.push(format!(
+                    "{}{}",
+                    INDENT,
+                    paint("stacktrace.library", &folded)
+                ));
+                i += repeated;
+            }
+        }
+    }
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, render, Frame, Line};
+    use crate::config::Stacktrace;
+
+    fn frame(function: &str, location: &str) -> Line {
+        Line::Frame(Frame {
+            function: function.to_string(),
+            location: Some(location.to_string()),
+            code: None,
+        })
+    }
+
+    #[test]
+    fn stacktrace_shapes() {
+        let go = "panic: boom\n\ngoroutine 1 [running]:\nmain.handler(...)\n\t/app/main.go:12 +0x1d\nruntime.main()\n\t/usr/local/go/src/runtime/proc.go:250 +0x207";
+        assert_eq!(
+            vec![
+                Line::Text("panic: boom".to_string()),
+                Line::Text("goroutine 1 [running]:".to_string()),
+                frame("main.handler(...)", "/app/main.go:12"),
+                frame("runtime.main()", "/usr/local/go/src/runtime/proc.go:250"),
+            ],
+            parse(go)
+        );
+
+        let python = "Traceback (most recent call last):\n  File \"/app/main.py\", line 10, in handler\n    run()\nValueError: bad";
+        assert_eq!(
+            vec![
+                Line::Text("Traceback (most recent call last):".to_string()),
+                Line::Frame(Frame {
+                    function: "handler".to_string(),
+                    location: Some("/app/main.py:10".to_string()),
+                    code: Some("run()".to_string()),
+                }),
+                Line::Text("ValueError: bad".to_string()),
+            ],
+            parse(python)
+        );
+
+        let rust = "stack backtrace:\n   0: std::panicking::begin_panic\n             at /rustc/abc/library/std/src/panicking.rs:616:12\n   1: app::main\n             at ./src/main.rs:2:5";
+        assert_eq!(
+            frame("app::main", "./src/main.rs:2:5"),
+            parse(rust).remove(2)
+        );
+    }
+
+    #[test]
+    fn stacktrace_render() {
+        let java = "java.lang.IllegalStateException: boom\n\
+            \tat com.example.Service.run(Service.java:42)\n\
+            \tat com.example.Service.run(Service.java:42)\n\
+            \tat org.springframework.web.Dispatcher.dispatch(Dispatcher.java:10)\n\
+            \tat java.base/java.lang.Thread.run(Thread.java:829)\n\
+            \t... 12 more";
+        let options = Stacktrace {
+            app_packages: vec!["com.example.".to_string()],
+            collapse: true,
+        };
+        assert_eq!(
+            vec![
+                "    java.lang.IllegalStateException: boom",
+                "    at com.example.Service.run (Service.java:42)",
+                "    ... same frame 1 more times",
+                "    ... 2 library frames",
+                "    ... 12 more",
+            ],
+            render(java, &options, None)
+        );
+    }
+}
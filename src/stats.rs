@@ -0,0 +1,482 @@
+use crate::{
+    level::Level,
+    output::{Output, Record},
+    timestamp,
+};
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+pub const STATS_FORMATS: [&str; 2] = ["table", "json"];
+
+const PERCENTILES: [usize; 4] = [50, 90, 95, 99];
+
+// What `falog stats` computes.
+pub struct StatsOptions {
+    // Records are counted by the values of each group of fields separately,
+    // e.g. `[["level"], ["method", "status"]]`.
+    pub groups: Vec<Vec<String>>,
+    // How many of the most common values of a group are shown.
+    pub top: usize,
+    // Fields holding numbers, e.g. request durations.
+    pub numeric: Vec<String>,
+    // Records are also counted per this long interval, if set. It's widened to a multiple
+    // when there would be more than MAX_BUCKETS intervals.
+    pub interval: Option<TimeDelta>,
+    pub json: bool,
+}
+
+// Counts the records instead of writing them, and writes a summary at the end.
+pub struct StatsOutput {
+    writer: Box<dyn Write + Send>,
+    options: StatsOptions,
+    records: u64,
+    // Records that have a level, errors among them.
+    leveled: u64,
+    errors: u64,
+    // Counts of each group by the values of its fields.
+    groups: Vec<HashMap<Vec<String>, u64>>,
+    // Values of each numeric field.
+    values: Vec<Vec<f64>>,
+    // Counts by the index of the interval since the Unix epoch.
+    intervals: BTreeMap<i64, u64>,
+    first: Option<DateTime<FixedOffset>>,
+    last: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    records: u64,
+    errors: u64,
+    // Share of the records with a level that are errors or worse.
+    error_rate: Option<f64>,
+    from: Option<String>,
+    to: Option<String>,
+    groups: Vec<GroupReport>,
+    fields: Vec<FieldReport>,
+    intervals: Vec<IntervalReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct GroupReport {
+    by: Vec<String>,
+    top: Vec<GroupCount>,
+    // Values that didn't make it to the top.
+    other_values: usize,
+    other_records: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct GroupCount {
+    values: Vec<String>,
+    count: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct FieldReport {
+    field: String,
+    count: usize,
+    min: Option<f64>,
+    max: Option<f64>,
+    avg: Option<f64>,
+    p50: Option<f64>,
+    p90: Option<f64>,
+    p95: Option<f64>,
+    p99: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+struct IntervalReport {
+    start: String,
+    count: u64,
+}
+
+impl StatsOutput {
+    pub fn new(writer: Box<dyn Write + Send>, options: StatsOptions) -> Self {
+        Self {
+            writer,
+            groups: vec![HashMap::new(); options.groups.len()],
+            values: vec![Vec::new(); options.numeric.len()],
+            options,
+            records: 0,
+            leveled: 0,
+            errors: 0,
+            intervals: BTreeMap::new(),
+            first: None,
+            last: None,
+        }
+    }
+
+    fn report(&self) -> Report {
+        let error_rate = (self.leveled > 0).then(|| self.errors as f64 / self.leveled as f64);
+        let groups = self
+            .options
+            .groups
+            .iter()
+            .zip(&self.groups)
+            .map(|(by, counts)| self.group_report(by, counts))
+            .collect();
+        let fields = self
+            .options
+            .numeric
+            .iter()
+            .zip(&self.values)
+            .map(|(field, values)| field_report(field, values))
+            .collect();
+
+        Report {
+            records: self.records,
+            errors: self.errors,
+            error_rate,
+            from: self.first.map(|time| time.to_rfc3339()),
+            to: self.last.map(|time| time.to_rfc3339()),
+            groups,
+            fields,
+            intervals: self.interval_reports(),
+        }
+    }
+
+    fn group_report(&self, by: &[String], counts: &HashMap<Vec<String>, u64>) -> GroupReport {
+        let mut sorted: Vec<_> = counts.iter().collect();
+        // Most common first, ties by value so that the order is stable.
+        sorted.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        let other = sorted.split_off(sorted.len().min(self.options.top));
+        GroupReport {
+            by: by.to_vec(),
+            top: sorted
+                .into_iter()
+                .map(|(values, count)| GroupCount {
+                    values: values.clone(),
+                    count: *count,
+                })
+                .collect(),
+            other_values: other.len(),
+            other_records: other.iter().map(|(_, count)| **count).sum(),
+        }
+    }
+
+    fn interval_reports(&self) -> Vec<IntervalReport> {
+        let (Some(interval), Some(zone)) = (self.options.interval, self.first) else {
+            return Vec::new();
+        };
+        let (Some((&first, _)), Some((&last, _))) = (
+            self.intervals.first_key_value(),
+            self.intervals.last_key_value(),
+        ) else {
+            return Vec::new();
+        };
+        let range = timestamp::BucketRange::new(first, last, interval);
+        let format = if range.interval.num_milliseconds() % 1000 == 0 {
+            "%Y-%m-%dT%H:%M:%S%:z"
+        } else {
+            "%Y-%m-%dT%H:%M:%S%.3f%:z"
+        };
+        let mut counts = BTreeMap::new();
+        for (&index, &count) in &self.intervals {
+            *counts.entry(range.widen(index)).or_insert(0) += count;
+        }
+
+        // Intervals are shown in the time zone of the earliest record. Intervals without
+        // records are shown too, with a count of 0.
+        range
+            .indexes()
+            .filter_map(|index| {
+                let start = timestamp::bucket_start(index, range.interval, zone.offset())?;
+                Some(IntervalReport {
+                    start: start.format(format).to_string(),
+                    count: counts.get(&index).copied().unwrap_or(0),
+                })
+            })
+            .collect()
+    }
+
+    fn write_table(&mut self, report: &Report) -> io::Result<()> {
+        let mut summary = vec![vec!["records".to_string(), report.records.to_string()]];
+        if let Some(rate) = report.error_rate {
+            summary.push(vec![
+                "errors".to_string(),
+                format!(
+                    "{} ({} of records with a level)",
+                    report.errors,
+                    percent(rate)
+                ),
+            ]);
+        }
+        if let (Some(from), Some(to)) = (&report.from, &report.to) {
+            summary.push(vec!["from".to_string(), from.clone()]);
+            summary.push(vec!["to".to_string(), to.clone()]);
+        }
+        write_rows(&mut self.writer, None, &summary, 2)?;
+
+        for group in &report.groups {
+            let mut header: Vec<_> = group.by.iter().map(|field| field.to_uppercase()).collect();
+            header.extend(["COUNT".to_string(), "SHARE".to_string()]);
+            let mut rows: Vec<_> = group
+                .top
+                .iter()
+                .map(|count| {
+                    let mut row = count.values.clone();
+                    row.push(count.count.to_string());
+                    row.push(percent(count.count as f64 / report.records as f64));
+                    row
+                })
+                .collect();
+            if group.other_values > 0 {
+                let mut row = vec![String::new(); group.by.len()];
+                row[0] = format!("({} more)", group.other_values);
+                row.push(group.other_records.to_string());
+                row.push(percent(group.other_records as f64 / report.records as f64));
+                rows.push(row);
+            }
+            writeln!(self.writer)?;
+            write_rows(&mut self.writer, Some(header), &rows, group.by.len())?;
+        }
+
+        if !report.fields.is_empty() {
+            let header = [
+                "FIELD", "COUNT", "MIN", "AVG", "P50", "P90", "P95", "P99", "MAX",
+            ]
+            .map(String::from)
+            .to_vec();
+            let rows: Vec<_> = report
+                .fields
+                .iter()
+                .map(|field| {
+                    let mut row = vec![field.field.clone(), field.count.to_string()];
+                    row.extend(
+                        [
+                            field.min, field.avg, field.p50, field.p90, field.p95, field.p99,
+                            field.max,
+                        ]
+                        .map(|value| value.map(number).unwrap_or_else(|| "-".to_string())),
+                    );
+                    row
+                })
+                .collect();
+            writeln!(self.writer)?;
+            write_rows(&mut self.writer, Some(header), &rows, 1)?;
+        }
+
+        if !report.intervals.is_empty() {
+            let header = vec!["INTERVAL".to_string(), "COUNT".to_string()];
+            let rows: Vec<_> = report
+                .intervals
+                .iter()
+                .map(|interval| vec![interval.start.clone(), interval.count.to_string()])
+                .collect();
+            writeln!(self.writer)?;
+            write_rows(&mut self.writer, Some(header), &rows, 1)?;
+        }
+        Ok(())
+    }
+}
+
+// The value of a field to group by. Levels are normalized so that `info` and `INFO` are
+// counted together, and `source` is where the record came from unless there's such a field.
+pub fn group_value(record: &Record, field: &str) -> String {
+    match (field, record.value(field)) {
+        ("level", Some(value)) => Level::parse(value)
+            .map(|level| level.as_str().to_string())
+            .unwrap_or_else(|| value.to_string()),
+        (_, Some(value)) => value.to_string(),
+        ("source", None) => record.source_name(),
+        (_, None) => "-".to_string(),
+    }
+}
+
+fn field_report(field: &str, values: &[f64]) -> FieldReport {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    // Nearest-rank percentiles.
+    let [p50, p90, p95, p99] = PERCENTILES.map(|p| {
+        let rank = (p * sorted.len()).div_ceil(100);
+        sorted.get(rank.saturating_sub(1)).copied()
+    });
+    FieldReport {
+        field: field.to_string(),
+        count: sorted.len(),
+        min: sorted.first().copied(),
+        max: sorted.last().copied(),
+        avg: (!sorted.is_empty()).then(|| sorted.iter().sum::<f64>() / sorted.len() as f64),
+        p50,
+        p90,
+        p95,
+        p99,
+    }
+}
+
+// Writes aligned rows. The first `text_columns` columns are aligned left, numbers right.
+fn write_rows(
+    writer: &mut dyn Write,
+    header: Option<Vec<String>>,
+    rows: &[Vec<String>],
+    text_columns: usize,
+) -> io::Result<()> {
+    let lines: Vec<&Vec<String>> = header.iter().chain(rows).collect();
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            lines
+                .iter()
+                .filter_map(|line| line.get(i))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    for line in lines {
+        let cells: Vec<String> = line
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                if i < text_columns {
+                    format!("{:<1$}", cell, widths[i])
+                } else {
+                    format!("{:>1$}", cell, widths[i])
+                }
+            })
+            .collect();
+        writeln!(writer, "{}", cells.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+// `12.5%`
+fn percent(share: f64) -> String {
+    format!("{}%", number(share * 100.0))
+}
+
+// At most 3 decimals, without trailing zeros.
+fn number(value: f64) -> String {
+    let formatted = format!("{:.3}", value);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+impl Output for StatsOutput {
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        self.records += 1;
+        if let Some(level) = record.level() {
+            self.leveled += 1;
+            if level >= Level::Error {
+                self.errors += 1;
+            }
+        }
+
+        for (by, counts) in self.options.groups.iter().zip(&mut self.groups) {
+            let key = by.iter().map(|field| group_value(record, field)).collect();
+            *counts.entry(key).or_insert(0) += 1;
+        }
+
+        for (field, values) in self.options.numeric.iter().zip(&mut self.values) {
+            if let Some(value) = record
+                .value(field)
+                .and_then(|v| v.trim().parse::<f64>().ok())
+            {
+                if value.is_finite() {
+                    values.push(value);
+                }
+            }
+        }
+
+        if let Some(time) = record.timestamp() {
+            self.first = Some(self.first.map_or(time, |first| first.min(time)));
+            self.last = Some(self.last.map_or(time, |last| last.max(time)));
+            if let Some(interval) = self.options.interval {
+                *self
+                    .intervals
+                    .entry(timestamp::bucket(time, interval))
+                    .or_insert(0) += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let report = self.report();
+        if self.options.json {
+            serde_json::to_writer_pretty(&mut self.writer, &report)?;
+            writeln!(self.writer)?;
+        } else {
+            self.write_table(&report)?;
+        }
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StatsOptions, StatsOutput};
+    use crate::output::{Output, TestRecord};
+    use chrono::TimeDelta;
+
+    #[test]
+    fn stats_report() {
+        let options = StatsOptions {
+            groups: vec![vec!["level".to_string()], vec!["path".to_string()]],
+            top: 1,
+            numeric: vec!["duration".to_string()],
+            interval: Some(TimeDelta::minutes(1)),
+            json: true,
+        };
+        let mut stats = StatsOutput::new(Box::new(std::io::sink()), options);
+        for (ts, level, path, duration) in [
+            ("2024-01-01T10:00:10Z", "info", "/a", "10"),
+            ("2024-01-01T10:00:50Z", "INFO", "/b", "30"),
+            ("2024-01-01T10:02:00Z", "error", "/a", "20"),
+            ("2024-01-01T10:02:30Z", "warn", "/c", "-"),
+        ] {
+            let record = TestRecord::new(&[
+                ("ts", ts),
+                ("level", level),
+                ("path", path),
+                ("duration", duration),
+            ])
+            .timestamp("ts", "rfc3339");
+            stats.write(&record.record()).unwrap();
+        }
+
+        let report = stats.report();
+        assert_eq!(
+            (4, 1, Some(0.25)),
+            (report.records, report.errors, report.error_rate)
+        );
+        assert_eq!(vec!["INFO"], report.groups[0].top[0].values);
+        assert_eq!(2, report.groups[0].top[0].count);
+        assert_eq!(
+            (2, 2),
+            (
+                report.groups[1].other_values,
+                report.groups[1].other_records
+            )
+        );
+
+        let duration = &report.fields[0];
+        assert_eq!(3, duration.count);
+        assert_eq!((Some(10.0), Some(30.0)), (duration.min, duration.max));
+        assert_eq!(
+            (Some(20.0), Some(20.0), Some(30.0)),
+            (duration.avg, duration.p50, duration.p90)
+        );
+
+        let intervals: Vec<_> = report
+            .intervals
+            .iter()
+            .map(|interval| (interval.start.as_str(), interval.count))
+            .collect();
+        assert_eq!(
+            vec![
+                ("2024-01-01T10:00:00+00:00", 2),
+                ("2024-01-01T10:01:00+00:00", 0),
+                ("2024-01-01T10:02:00+00:00", 2),
+            ],
+            intervals
+        );
+    }
+}
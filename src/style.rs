@@ -0,0 +1,296 @@
+use anyhow::bail;
+use std::{
+    env,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+pub const RESET: &str = "\x1b[0m";
+
+const COLOR_NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "gray",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+];
+
+// RGB values of the 16 basic colors, used to pick the closest one when downgrading.
+const BASIC_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+// How many colors the terminal can show. Colors are downgraded to fit.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum ColorDepth {
+    Basic = 0,
+    Ansi256 = 1,
+    TrueColor = 2,
+}
+
+static COLOR_DEPTH: AtomicU8 = AtomicU8::new(ColorDepth::TrueColor as u8);
+
+impl ColorDepth {
+    // Guesses from `COLORTERM` and `TERM`.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return Self::TrueColor;
+        }
+        if env::var("TERM").unwrap_or_default().contains("256color") {
+            return Self::Ansi256;
+        }
+        Self::Basic
+    }
+
+    fn get() -> Self {
+        match COLOR_DEPTH.load(Ordering::Relaxed) {
+            0 => Self::Basic,
+            1 => Self::Ansi256,
+            _ => Self::TrueColor,
+        }
+    }
+}
+
+// Sets the color depth every style is written with.
+pub fn set_color_depth(depth: ColorDepth) {
+    COLOR_DEPTH.store(depth as u8, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    // One of the 16 basic terminal colors, indexed like `COLOR_NAMES`.
+    Named(u8),
+    // One of the 256 colors of xterm.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    // A color name like `red`, an index of the 256 color palette like `208`, or `#rrggbb`.
+    pub fn parse(name: &str) -> Option<Self> {
+        if let Some(hex) = name.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return None;
+            }
+            let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            return Some(Self::Rgb(component(0)?, component(2)?, component(4)?));
+        }
+        if let Ok(index) = name.parse::<u8>() {
+            return Some(Self::Indexed(index));
+        }
+
+        let name = if name == "grey" { "gray" } else { name };
+        COLOR_NAMES
+            .iter()
+            .position(|n| *n == name)
+            .map(|i| Self::Named(i as u8))
+    }
+
+    fn code(&self, background: bool) -> String {
+        let depth = ColorDepth::get();
+        let base = if background { 40 } else { 30 };
+        match self.downgrade(depth) {
+            Self::Named(i) if i < 8 => (base + i as u32).to_string(),
+            Self::Named(i) => (base + 60 + (i - 8) as u32).to_string(),
+            Self::Indexed(i) => format!("{};5;{}", base + 8, i),
+            Self::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        }
+    }
+
+    // The color as the terminal can show it, see `set_color_depth`.
+    pub fn for_terminal(self) -> Self {
+        self.downgrade(ColorDepth::get())
+    }
+
+    fn downgrade(self, depth: ColorDepth) -> Self {
+        match (self, depth) {
+            (Self::Indexed(i), _) if i < 16 => Self::Named(i),
+            (Self::Rgb(r, g, b), ColorDepth::Ansi256) => Self::Indexed(rgb_to_256(r, g, b)),
+            (Self::Rgb(r, g, b), ColorDepth::Basic) => Self::Named(rgb_to_basic(r, g, b)),
+            (Self::Indexed(i), ColorDepth::Basic) => {
+                let (r, g, b) = indexed_to_rgb(i);
+                Self::Named(rgb_to_basic(r, g, b))
+            }
+            (color, _) => color,
+        }
+    }
+}
+
+// Levels of the 6x6x6 color cube of the 256 color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn indexed_to_rgb(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..=15 => BASIC_RGB[i as usize],
+        16..=231 => {
+            let i = i - 16;
+            (
+                CUBE_LEVELS[(i / 36) as usize],
+                CUBE_LEVELS[(i / 6 % 6) as usize],
+                CUBE_LEVELS[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let gray = 8 + (i - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn rgb_to_256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |c: u8| {
+        CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, level)| (**level as i32 - c as i32).abs())
+            .map(|(i, _)| i as u8)
+            .unwrap()
+    };
+    let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+    // The gray ramp is closer for grayish colors.
+    let average = ((r as u32 + g as u32 + b as u32) / 3) as u8;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23);
+    if distance((r, g, b), indexed_to_rgb(gray)) < distance((r, g, b), indexed_to_rgb(cube)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn rgb_to_basic(r: u8, g: u8, b: u8) -> u8 {
+    (0..16)
+        .min_by_key(|i| distance((r, g, b), BASIC_RGB[*i as usize]))
+        .unwrap()
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    // Parses space separated words: `bold`, `dim`, `italic`, `underline`,
+    // a foreground color like `red`, `208` or `#ff8700`, and a background color like `on_blue`.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut style = Self::default();
+        for word in spec.split_whitespace() {
+            match word {
+                "bold" => style.bold = true,
+                "dim" => style.dim = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                word => {
+                    let (background, name) = match word.strip_prefix("on_") {
+                        Some(name) => (true, name),
+                        None => (false, word),
+                    };
+                    let color = match Color::parse(name) {
+                        Some(color) => color,
+                        None => bail!("unknown style `{}`", word),
+                    };
+                    if background {
+                        style.bg = Some(color);
+                    } else {
+                        style.fg = Some(color);
+                    }
+                }
+            }
+        }
+        Ok(style)
+    }
+
+    pub fn is_plain(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // The escape sequence that turns this style on.
+    pub fn prefix(&self) -> String {
+        let mut codes = Vec::new();
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if self.dim {
+            codes.push("2".to_string());
+        }
+        if self.italic {
+            codes.push("3".to_string());
+        }
+        if self.underline {
+            codes.push("4".to_string());
+        }
+        if let Some(fg) = &self.fg {
+            codes.push(fg.code(false));
+        }
+        if let Some(bg) = &self.bg {
+            codes.push(bg.code(true));
+        }
+        if codes.is_empty() {
+            return String::new();
+        }
+        format!("\x1b[{}m", codes.join(";"))
+    }
+
+    pub fn paint(&self, s: &str) -> String {
+        if self.is_plain() || s.is_empty() {
+            return s.to_string();
+        }
+        format!("{}{}{}", self.prefix(), s, RESET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, ColorDepth};
+
+    #[test]
+    fn color_downgrade() {
+        let orange = Color::parse("#ff8700").unwrap();
+        assert_eq!(Color::Rgb(255, 135, 0), orange);
+        assert_eq!(Color::Indexed(208), orange.downgrade(ColorDepth::Ansi256));
+        assert_eq!(Color::Named(3), orange.downgrade(ColorDepth::Basic));
+        assert_eq!(
+            Color::Indexed(244),
+            Color::Rgb(128, 128, 128).downgrade(ColorDepth::Ansi256)
+        );
+        assert_eq!(
+            Color::Named(1),
+            Color::Indexed(1).downgrade(ColorDepth::TrueColor)
+        );
+    }
+}
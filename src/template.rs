@@ -0,0 +1,524 @@
+use crate::{
+    config::Fields,
+    highlight::Highlighter,
+    output::Record,
+    output_logfmt::{escape_key, escape_value},
+    style::{self, Style},
+    theme::Theme,
+    timestamp::{TimeDisplay, TimeOptions},
+};
+use anyhow::{anyhow, bail};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset,
+};
+use std::collections::HashSet;
+
+// Output templates, e.g. `{ts:%H:%M:%S} {@level}{level:>5}{@reset} [{source}] {message} {rest}`
+//
+// - `{name}` is the value of a field. `message`, `level`, `timestamp` (or `ts`) and
+//   `stacktrace` refer to the fields set in the format's `fields`, or to a field of that
+//   name if the format doesn't set them.
+// - `{source}` is the file path, `stdin` or the source's label, `{raw}` is the record as it was read and
+//   `{rest}` is every field the template doesn't mention, as `key=value` pairs.
+// - `{name|default}` uses `default` when the field is missing or empty.
+// - `{name:<10}`, `{name:>10}` and `{name:^10}` pad to a width, `{name:.20}` truncates to
+//   20 characters. They can be combined: `{name:<20.20}`.
+// - `{timestamp:%H:%M:%S}` parses the timestamp and formats it with a strftime pattern.
+//   Without a pattern, the timestamp is shown as set by `--time-format`, `--tz` and the like.
+// - `{@red bold}` turns on a style (see `Style::parse`) until `{@reset}`.
+//   `{@level}` uses the theme's color of the record's level.
+// - `{{` and `}}` are literal braces.
+// What a template is rendered with besides the record.
+#[derive(Default)]
+pub struct RenderContext<'a> {
+    // Colors are only used if there's a theme.
+    pub theme: Option<&'a Theme>,
+    // Applied to the message.
+    pub highlighter: Option<&'a Highlighter>,
+    pub time: Option<&'a TimeOptions>,
+    // The timestamp of the previous record, for showing the time since then.
+    pub previous: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Value {
+        reference: Reference,
+        default: Option<String>,
+        spec: Spec,
+    },
+    Style(Directive),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Reference {
+    Message,
+    Level,
+    Timestamp,
+    Stacktrace,
+    Field(String),
+    Source,
+    Raw,
+    Rest,
+}
+
+#[derive(Debug, Clone)]
+enum Directive {
+    Style(Style),
+    Level,
+    Reset,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone)]
+struct Spec {
+    align: Align,
+    width: Option<usize>,
+    precision: Option<usize>,
+    time_format: Option<String>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| anyhow!("unclosed `{{` in template `{}`", template))?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_part(&rest[..end])?);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => bail!("unmatched `}}` in template `{}`, use `}}}}`", template),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Self { parts })
+    }
+
+    // Checks that every field the template refers to exists. `known` is the set of fields the
+    // format can produce, or None if that can't be known upfront (e.g. JSON). Then any field
+    // may turn up, but one a letter or two off a field the format sets is taken as a typo.
+    pub fn validate(&self, fields: &Fields, known: Option<&[String]>) -> anyhow::Result<()> {
+        for part in &self.parts {
+            let Part::Value { reference, .. } = part else {
+                continue;
+            };
+            let Some(name) = field_name(reference, fields) else {
+                continue;
+            };
+            match known {
+                Some(known) => {
+                    if !known.iter().any(|k| k == name) {
+                        bail!(
+                            "template refers to the unknown field `{}`. known fields: {}",
+                            name,
+                            known.join(", ")
+                        );
+                    }
+                }
+                None => {
+                    if let Some(meant) = misspelled(name, fields) {
+                        bail!(
+                            "template refers to the unknown field `{}`. did you mean `{}`?",
+                            name,
+                            meant
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, record: &Record, context: &RenderContext) -> String {
+        let theme = context.theme;
+        let fields = record.format_fields;
+        let mut out = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => out.push_str(literal),
+                Part::Style(directive) => {
+                    let theme = match theme {
+                        Some(theme) => theme,
+                        None => continue,
+                    };
+                    let style = match directive {
+                        Directive::Style(style) => style,
+                        Directive::Level => match record.level() {
+                            Some(level) => theme.level(level),
+                            None => continue,
+                        },
+                        Directive::Reset => {
+                            out.push_str(style::RESET);
+                            continue;
+                        }
+                    };
+                    out.push_str(&style.prefix());
+                }
+                Part::Value {
+                    reference,
+                    default,
+                    spec,
+                } => {
+                    let mut value = match reference {
+                        Reference::Source => record.source_name(),
+                        Reference::Raw => record.raw.trim_end_matches(['\n', '\r']).to_string(),
+                        Reference::Rest => self.rest(record),
+                        reference => field_name(reference, fields)
+                            .and_then(|name| record.fields.get(name))
+                            .map(|field| field.value.clone())
+                            .unwrap_or_default(),
+                    };
+
+                    if *reference == Reference::Timestamp {
+                        if let Some(time) = record.timestamp() {
+                            let default = TimeOptions::default();
+                            let options = context.time.unwrap_or(&default);
+                            let display = TimeDisplay::new(options, time, context.previous);
+                            if let Some(shown) = display.format(spec.time_format.as_deref()) {
+                                value = shown;
+                            }
+                        }
+                    }
+                    if value.is_empty() {
+                        if let Some(default) = default {
+                            value = default.clone();
+                        }
+                    }
+                    let highlight = match (theme, context.highlighter) {
+                        (Some(theme), Some(highlighter)) if *reference == Reference::Message => {
+                            Some((highlighter, theme))
+                        }
+                        _ => None,
+                    };
+                    out.push_str(&spec.apply(value, highlight));
+                }
+            }
+        }
+
+        let mut out = out.trim_end().to_string();
+        if theme.is_some() {
+            out.push_str(style::RESET);
+        }
+        out
+    }
+
+    // Whether the template shows the stack trace itself. If not, it's shown below the record.
+    pub fn shows_stacktrace(&self) -> bool {
+        self.parts.iter().any(|part| {
+            matches!(
+                part,
+                Part::Value {
+                    reference: Reference::Stacktrace,
+                    ..
+                }
+            )
+        })
+    }
+
+    fn rest(&self, record: &Record) -> String {
+        // The stack trace is either mentioned or shown below the record.
+        let mentioned: HashSet<&str> = self
+            .parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Value { reference, .. } => field_name(reference, record.format_fields),
+                _ => None,
+            })
+            .chain(field_name(&Reference::Stacktrace, record.format_fields))
+            .collect();
+
+        record
+            .fields
+            .iter()
+            .filter(|(name, _)| !mentioned.contains(name.as_str()))
+            .map(|(name, field)| format!("{}={}", escape_key(name), escape_value(&field.value)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+// The name of the field a reference reads, if it reads one.
+fn field_name<'a>(reference: &'a Reference, fields: &'a Fields) -> Option<&'a str> {
+    let name = match reference {
+        Reference::Message => fields.message.as_deref().unwrap_or("message"),
+        Reference::Level => fields.level.as_deref().unwrap_or("level"),
+        Reference::Timestamp => match &fields.timestamp {
+            Some(timestamp) => timestamp.name.as_str(),
+            None => "timestamp",
+        },
+        Reference::Stacktrace => fields.stacktrace.as_deref().unwrap_or("stacktrace"),
+        Reference::Field(name) => name.as_str(),
+        Reference::Source | Reference::Raw | Reference::Rest => return None,
+    };
+    Some(name)
+}
+
+// The field `name` is likely a misspelling of: `message`, `level`, `timestamp`, `stacktrace`
+// or a field named in `fields`. Short names are left alone, they're too close to everything.
+fn misspelled(name: &str, fields: &Fields) -> Option<String> {
+    let max_distance = match name.chars().count() {
+        0..=4 => return None,
+        5..=7 => 1,
+        _ => 2,
+    };
+    let mut candidates = fields.included();
+    candidates.extend(["message", "level", "timestamp", "stacktrace"].map(String::from));
+    if candidates.iter().any(|candidate| candidate == name) {
+        return None;
+    }
+    candidates
+        .into_iter()
+        .find(|candidate| edit_distance(name, candidate) <= max_distance)
+}
+
+// Levenshtein distance: how many characters have to be inserted, removed or replaced to turn
+// `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn parse_part(inner: &str) -> anyhow::Result<Part> {
+    if let Some(directive) = inner.strip_prefix('@') {
+        let directive = match directive.trim() {
+            "reset" => Directive::Reset,
+            "level" => Directive::Level,
+            style => Directive::Style(Style::parse(style)?),
+        };
+        return Ok(Part::Style(directive));
+    }
+
+    let (reference, spec) = match inner.split_once(':') {
+        Some((reference, spec)) => (reference, Some(spec)),
+        None => (inner, None),
+    };
+    let (name, default) = match reference.split_once('|') {
+        Some((name, default)) => (name.trim(), Some(default.to_string())),
+        None => (reference.trim(), None),
+    };
+
+    let reference = match name {
+        "" => bail!("empty field reference `{{{}}}`", inner),
+        "message" => Reference::Message,
+        "level" => Reference::Level,
+        "timestamp" | "ts" => Reference::Timestamp,
+        "stacktrace" => Reference::Stacktrace,
+        "source" => Reference::Source,
+        "raw" => Reference::Raw,
+        "rest" => Reference::Rest,
+        name => Reference::Field(name.to_string()),
+    };
+
+    let spec = Spec::parse(spec.unwrap_or(""))?;
+    if spec.time_format.is_some() && reference != Reference::Timestamp {
+        bail!("`{{{}}}`: time formats only apply to the timestamp", inner);
+    }
+
+    Ok(Part::Value {
+        reference,
+        default,
+        spec,
+    })
+}
+
+impl Spec {
+    fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut parsed = Self {
+            align: Align::Left,
+            width: None,
+            precision: None,
+            time_format: None,
+        };
+
+        if spec.contains('%') {
+            if StrftimeItems::new(spec).any(|item| item == Item::Error) {
+                bail!("invalid time format `{}`", spec);
+            }
+            parsed.time_format = Some(spec.to_string());
+            return Ok(parsed);
+        }
+
+        let mut rest = spec;
+        if let Some(c) = rest.chars().next() {
+            let align = match c {
+                '<' => Some(Align::Left),
+                '>' => Some(Align::Right),
+                '^' => Some(Align::Center),
+                _ => None,
+            };
+            if let Some(align) = align {
+                parsed.align = align;
+                rest = &rest[1..];
+            }
+        }
+
+        let (width, precision) = match rest.split_once('.') {
+            Some((width, precision)) => (width, Some(precision)),
+            None => (rest, None),
+        };
+        let number = |s: &str| {
+            s.parse::<usize>()
+                .map_err(|_| anyhow!("invalid format spec `{}`", spec))
+        };
+        if !width.is_empty() {
+            parsed.width = Some(number(width)?);
+        }
+        if let Some(precision) = precision {
+            parsed.precision = Some(number(precision)?);
+        }
+
+        Ok(parsed)
+    }
+
+    fn apply(&self, value: String, highlight: Option<(&Highlighter, &Theme)>) -> String {
+        let mut value = value;
+        if let Some(precision) = self.precision {
+            value = truncate(&value, precision);
+        }
+        // Padding is computed from the length without escape sequences.
+        let len = value.chars().count();
+        if let Some((highlighter, theme)) = highlight {
+            value = highlighter.highlight(&value, theme);
+        }
+
+        let width = match self.width {
+            Some(width) => width,
+            None => return value,
+        };
+        if len >= width {
+            return value;
+        }
+        let pad = width - len;
+        match self.align {
+            Align::Left => format!("{}{}", value, " ".repeat(pad)),
+            Align::Right => format!("{}{}", " ".repeat(pad), value),
+            Align::Center => format!(
+                "{}{}{}",
+                " ".repeat(pad / 2),
+                value,
+                " ".repeat(pad - pad / 2)
+            ),
+        }
+    }
+}
+
+// Truncates to `max` characters, the last one being `…` if anything was cut.
+pub fn truncate(value: &str, max: usize) -> String {
+    if value.chars().count() <= max {
+        return value.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let mut truncated: String = value.chars().take(max - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RenderContext, Template};
+    use crate::{
+        config::{Fields, TimestampField},
+        output::TestRecord,
+        source::SourceType,
+    };
+
+    #[test]
+    fn template_render() {
+        let format_fields = Fields {
+            message: Some("msg".to_string()),
+            timestamp: Some(TimestampField {
+                name: "ts".to_string(),
+                format: "unix".to_string(),
+            }),
+            ..Default::default()
+        };
+        let mut record = TestRecord::new(&[
+            ("ts", "1700000000"),
+            ("level", "warn"),
+            ("msg", "hello world"),
+            ("user", ""),
+            ("status", "500"),
+        ])
+        .format_fields(format_fields.clone());
+        record.source = SourceType::File("app.log".to_string());
+        let record = record.record();
+
+        let template =
+            Template::parse("{ts:%H:%M:%S} {level:>5} [{source}] {message:.8} {user|-} {{{rest}}}")
+                .unwrap();
+        assert_eq!(
+            "22:13:20  warn [app.log] hello w… - {status=500}",
+            template.render(&record, &RenderContext::default())
+        );
+        let mut labeled = TestRecord::new(&[]);
+        labeled.label = Some("edge".to_string());
+        let source = Template::parse("[{source}]").unwrap();
+        assert_eq!(
+            "[edge]",
+            source.render(&labeled.record(), &RenderContext::default())
+        );
+
+        let known = ["ts".to_string(), "msg".to_string()];
+        assert!(template.validate(&format_fields, Some(&known)).is_err());
+        // Fields of JSON can't be known, but typos of the ones the format sets can.
+        let validate = |text| {
+            Template::parse(text)
+                .unwrap()
+                .validate(&format_fields, None)
+        };
+        assert!(validate("{message} {user} {lvl} {status}").is_ok());
+        let e = validate("{mesage}").unwrap_err();
+        assert!(e.to_string().ends_with("did you mean `message`?"), "{}", e);
+        assert!(validate("{timestmp}").is_err());
+        assert!(Template::parse("{msg:%H}").is_err());
+        assert!(Template::parse("{msg").is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{config, level::Level, style::Style};
+use anyhow::{anyhow, bail};
+use std::collections::HashMap;
+
+// Everything a theme can style.
+const KEYS: [&str; 26] = [
+    "level.trace",
+    "level.debug",
+    "level.info",
+    "level.warn",
+    "level.error",
+    "level.fatal",
+    "timestamp",
+    "key",
+    "value",
+    "source",
+    "header",
+    "highlight.url",
+    "highlight.uuid",
+    "highlight.ip",
+    "highlight.quoted",
+    "highlight.duration",
+    "highlight.http_method",
+    "highlight.http_status.2xx",
+    "highlight.http_status.3xx",
+    "highlight.http_status.4xx",
+    "highlight.http_status.5xx",
+    "highlight.number",
+    "highlight.custom",
+    "stacktrace.text",
+    "stacktrace.app",
+    "stacktrace.library",
+];
+
+const DARK: [(&str, &str); 26] = [
+    ("level.trace", "gray"),
+    ("level.debug", "blue"),
+    ("level.info", "green"),
+    ("level.warn", "yellow"),
+    ("level.error", "red"),
+    ("level.fatal", "bold red"),
+    ("timestamp", "dim"),
+    ("key", "cyan"),
+    ("value", ""),
+    ("source", "magenta"),
+    ("header", "bold"),
+    ("highlight.url", "underline blue"),
+    ("highlight.uuid", "magenta"),
+    ("highlight.ip", "blue"),
+    ("highlight.quoted", "green"),
+    ("highlight.duration", "yellow"),
+    ("highlight.http_method", "bold"),
+    ("highlight.http_status.2xx", "green"),
+    ("highlight.http_status.3xx", "cyan"),
+    ("highlight.http_status.4xx", "yellow"),
+    ("highlight.http_status.5xx", "red"),
+    ("highlight.number", "cyan"),
+    ("highlight.custom", "black on_yellow"),
+    ("stacktrace.text", "red"),
+    ("stacktrace.app", "bold"),
+    ("stacktrace.library", "dim"),
+];
+
+// Darker colors that stay readable on a white background.
+const LIGHT: [(&str, &str); 26] = [
+    ("level.trace", "244"),
+    ("level.debug", "25"),
+    ("level.info", "28"),
+    ("level.warn", "130"),
+    ("level.error", "160"),
+    ("level.fatal", "bold 160"),
+    ("timestamp", "244"),
+    ("key", "30"),
+    ("value", ""),
+    ("source", "90"),
+    ("header", "bold"),
+    ("highlight.url", "underline 25"),
+    ("highlight.uuid", "90"),
+    ("highlight.ip", "25"),
+    ("highlight.quoted", "28"),
+    ("highlight.duration", "130"),
+    ("highlight.http_method", "bold"),
+    ("highlight.http_status.2xx", "28"),
+    ("highlight.http_status.3xx", "30"),
+    ("highlight.http_status.4xx", "130"),
+    ("highlight.http_status.5xx", "160"),
+    ("highlight.number", "30"),
+    ("highlight.custom", "on_229"),
+    ("stacktrace.text", "160"),
+    ("stacktrace.app", "bold"),
+    ("stacktrace.library", "244"),
+];
+
+pub const THEMES: [&str; 3] = ["dark", "light", "none"];
+
+// Styles of everything falog colors, by key, e.g. `level.error` or `highlight.url`.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    styles: HashMap<&'static str, Style>,
+}
+
+impl Theme {
+    // One of the bundled themes: `dark`, `light`, or `none` which doesn't style anything.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let styles = match name {
+            "dark" => DARK,
+            "light" => LIGHT,
+            "none" => KEYS.map(|key| (key, "")),
+            _ => return None,
+        };
+        let styles = styles
+            .into_iter()
+            .map(|(key, style)| (key, Style::parse(style).unwrap()))
+            .collect();
+        Some(Self { styles })
+    }
+
+    // The theme set in the config: a bundled theme with its styles overridden.
+    pub fn from_config(theme: &config::Theme) -> anyhow::Result<Self> {
+        let mut built = Self::builtin(&theme.name).ok_or_else(|| {
+            anyhow!(
+                "theme: unknown theme `{}`. bundled themes: {}",
+                theme.name,
+                THEMES.join(", ")
+            )
+        })?;
+        for (key, style) in &theme.styles {
+            let key = match KEYS.iter().find(|k| *k == key) {
+                Some(key) => *key,
+                None => bail!(
+                    "theme: unknown style `{}`. styles: {}",
+                    key,
+                    KEYS.join(", ")
+                ),
+            };
+            let style = Style::parse(style).map_err(|e| anyhow!("theme: `{}`: {}", key, e))?;
+            built.styles.insert(key, style);
+        }
+        Ok(built)
+    }
+
+    pub fn get(&self, key: &str) -> &Style {
+        &self.styles[key]
+    }
+
+    pub fn level(&self, level: Level) -> &Style {
+        self.get(match level {
+            Level::Trace => "level.trace",
+            Level::Debug => "level.debug",
+            Level::Info => "level.info",
+            Level::Warn => "level.warn",
+            Level::Error => "level.error",
+            Level::Fatal => "level.fatal",
+        })
+    }
+
+    pub fn paint(&self, key: &str, s: &str) -> String {
+        self.get(key).paint(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Theme, DARK, KEYS, LIGHT};
+    use crate::config;
+    use std::collections::HashMap;
+
+    #[test]
+    fn theme_from_config() {
+        for styles in [DARK, LIGHT] {
+            assert_eq!(KEYS.to_vec(), styles.map(|(key, _)| key).to_vec());
+        }
+
+        let mut theme = config::Theme {
+            name: "light".to_string(),
+            styles: HashMap::from([("source".to_string(), "bold #ff8700".to_string())]),
+        };
+        let built = Theme::from_config(&theme).unwrap();
+        assert_eq!(
+            "\x1b[1;38;2;255;135;0m[a]\x1b[0m",
+            built.paint("source", "[a]")
+        );
+
+        theme.styles.insert("nope".to_string(), "red".to_string());
+        assert!(Theme::from_config(&theme).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use anyhow::{anyhow, bail};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset, Local, NaiveDateTime, TimeDelta, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use std::ops::RangeInclusive;
+
+// Parses `value` according to the `format` of a `TimestampField`:
+// `unix` (seconds, fractions allowed), `unix_milli`, `unix_micro`, `unix_nano`,
+// `rfc3339`, `rfc2822`, or a strftime-like pattern such as `%Y-%m-%d %H:%M:%S`.
+// Patterns without a time zone are taken as UTC.
+pub fn parse(value: &str, format: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    let utc = match format {
+        "unix" => {
+            let seconds: f64 = value.parse().ok()?;
+            let nanos = (seconds.fract() * 1e9).round() as u32;
+            Utc.timestamp_opt(seconds.trunc() as i64, nanos).single()?
+        }
+        "unix_milli" => Utc.timestamp_millis_opt(value.parse().ok()?).single()?,
+        "unix_micro" => Utc.timestamp_micros(value.parse().ok()?).single()?,
+        "unix_nano" => Utc.timestamp_nanos(value.parse().ok()?),
+        "rfc3339" => return DateTime::parse_from_rfc3339(value).ok(),
+        "rfc2822" => return DateTime::parse_from_rfc2822(value).ok(),
+        pattern => {
+            if let Ok(time) = DateTime::parse_from_str(value, pattern) {
+                return Some(time);
+            }
+            NaiveDateTime::parse_from_str(value, pattern)
+                .ok()?
+                .and_utc()
+        }
+    };
+    Some(utc.fixed_offset())
+}
+
+// Checks that `format` is one `parse` understands.
+pub fn check_format(format: &str) -> anyhow::Result<()> {
+    match format {
+        "unix" | "unix_milli" | "unix_micro" | "unix_nano" | "rfc3339" | "rfc2822" => Ok(()),
+        pattern if !pattern.contains('%') => bail!(
+            "unknown timestamp format `{}`. use unix, unix_milli, unix_micro, unix_nano, rfc3339, rfc2822 or a strftime pattern",
+            pattern
+        ),
+        pattern if StrftimeItems::new(pattern).any(|item| item == Item::Error) => {
+            bail!("invalid strftime pattern `{}`", pattern)
+        }
+        _ => Ok(()),
+    }
+}
+
+// A positive duration like `500ms`, `30s`, `5m`, `1h` or `1d`.
+pub fn parse_duration(value: &str) -> anyhow::Result<TimeDelta> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow!("duration `{}` needs a unit: ms, s, m, h or d", value))?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| anyhow!("invalid duration `{}`", value))?;
+    let duration = match unit {
+        "ms" => TimeDelta::try_milliseconds(amount),
+        "s" => TimeDelta::try_seconds(amount),
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        _ => bail!("unknown unit in duration `{}`, use ms, s, m, h or d", value),
+    };
+    match duration {
+        Some(duration) if duration > TimeDelta::zero() => Ok(duration),
+        _ => bail!("invalid duration `{}`", value),
+    }
+}
+
+// The index of the `interval` long bucket `time` falls in, counting from the Unix epoch.
+pub fn bucket(time: DateTime<FixedOffset>, interval: TimeDelta) -> i64 {
+    time.timestamp_millis()
+        .div_euclid(interval.num_milliseconds())
+}
+
+// When the bucket at `index` starts, in the time zone of `offset`.
+pub fn bucket_start(
+    index: i64,
+    interval: TimeDelta,
+    offset: &FixedOffset,
+) -> Option<DateTime<FixedOffset>> {
+    let start = DateTime::from_timestamp_millis(index.checked_mul(interval.num_milliseconds())?)?;
+    Some(start.with_timezone(offset))
+}
+
+// Buckets shown at most, longer spans of time get wider buckets.
+pub const MAX_BUCKETS: i64 = 1000;
+
+// The buckets from `first` to `last`, indexes of `interval` long buckets. If there would be
+// more than MAX_BUCKETS of them, they're widened to a multiple of `interval`.
+pub struct BucketRange {
+    pub interval: TimeDelta,
+    factor: i64,
+    first: i64,
+    last: i64,
+}
+
+impl BucketRange {
+    pub fn new(first: i64, last: i64, interval: TimeDelta) -> Self {
+        let count = |factor: i64| last.div_euclid(factor) - first.div_euclid(factor) + 1;
+        let mut factor = (count(1) - 1) / MAX_BUCKETS + 1;
+        // Widened buckets start at a multiple of their length, which can add one.
+        while count(factor) > MAX_BUCKETS {
+            factor += 1;
+        }
+        let millis = interval.num_milliseconds().saturating_mul(factor);
+        Self {
+            interval: TimeDelta::try_milliseconds(millis).unwrap_or(TimeDelta::MAX),
+            factor,
+            first,
+            last,
+        }
+    }
+
+    // The index of the widened bucket that the bucket at `index` is part of.
+    pub fn widen(&self, index: i64) -> i64 {
+        index.div_euclid(self.factor)
+    }
+
+    // Indexes of the widened buckets, every one from the first to the last.
+    pub fn indexes(&self) -> RangeInclusive<i64> {
+        self.widen(self.first)..=self.widen(self.last)
+    }
+}
+
+// Where timestamps are shown in.
+#[derive(Debug, Clone)]
+pub enum Zone {
+    // As they were logged.
+    Original,
+    Utc,
+    Local,
+    Named(Tz),
+}
+
+impl Zone {
+    // `UTC`, `local` or an IANA time zone like `Europe/Berlin`.
+    pub fn parse(name: &str) -> anyhow::Result<Self> {
+        match name {
+            "UTC" | "utc" => Ok(Self::Utc),
+            "local" => Ok(Self::Local),
+            name => name
+                .parse::<Tz>()
+                .map(Self::Named)
+                .map_err(|_| anyhow!("unknown time zone `{}`", name)),
+        }
+    }
+
+    fn convert(&self, time: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self {
+            Self::Original => time,
+            Self::Utc => time.with_timezone(&Utc).fixed_offset(),
+            Self::Local => time.with_timezone(&Local).fixed_offset(),
+            Self::Named(tz) => time.with_timezone(tz).fixed_offset(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Absolute,
+    // `3m ago`
+    Relative,
+    // Time since the previous record, e.g. `+1.250s`.
+    Delta,
+}
+
+// How timestamps are shown by the pretty and table outputs.
+#[derive(Debug, Clone)]
+pub struct TimeOptions {
+    // A strftime pattern.
+    pub format: Option<String>,
+    pub zone: Zone,
+    pub mode: Mode,
+}
+
+impl Default for TimeOptions {
+    fn default() -> Self {
+        Self {
+            format: None,
+            zone: Zone::Original,
+            mode: Mode::Absolute,
+        }
+    }
+}
+
+impl TimeOptions {
+    // Whether timestamps are shown as anything but the logged value.
+    pub fn is_set(&self) -> bool {
+        self.format.is_some() || !matches!(self.zone, Zone::Original) || self.mode != Mode::Absolute
+    }
+}
+
+// The timestamp of a record, ready to be shown.
+pub struct TimeDisplay<'a> {
+    options: &'a TimeOptions,
+    time: DateTime<FixedOffset>,
+    previous: Option<DateTime<FixedOffset>>,
+}
+
+impl<'a> TimeDisplay<'a> {
+    pub fn new(
+        options: &'a TimeOptions,
+        time: DateTime<FixedOffset>,
+        previous: Option<DateTime<FixedOffset>>,
+    ) -> Self {
+        Self {
+            options,
+            time: options.zone.convert(time),
+            previous,
+        }
+    }
+
+    // `format` is a strftime pattern that overrides the options, e.g. one given in a template.
+    // None if the timestamp should be shown as it was logged.
+    pub fn format(&self, format: Option<&str>) -> Option<String> {
+        if let Some(format) = format {
+            return Some(self.time.format(format).to_string());
+        }
+        if !self.options.is_set() {
+            return None;
+        }
+        let shown = match self.options.mode {
+            Mode::Relative => relative(Utc::now().fixed_offset() - self.time),
+            Mode::Delta => delta(self.time - self.previous.unwrap_or(self.time)),
+            Mode::Absolute => {
+                let format = self
+                    .options
+                    .format
+                    .as_deref()
+                    .unwrap_or("%Y-%m-%dT%H:%M:%S%.3f%:z");
+                self.time.format(format).to_string()
+            }
+        };
+        Some(shown)
+    }
+}
+
+// `3m ago`, or `in 3m` for times in the future.
+fn relative(elapsed: TimeDelta) -> String {
+    let seconds = elapsed.num_seconds();
+    if seconds == 0 {
+        return "now".to_string();
+    }
+    let amount = match seconds.unsigned_abs() {
+        s if s < 60 => format!("{}s", s),
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h", s / (60 * 60)),
+        s => format!("{}d", s / (24 * 60 * 60)),
+    };
+    if seconds > 0 {
+        format!("{} ago", amount)
+    } else {
+        format!("in {}", amount)
+    }
+}
+
+// `+0.250s`, `+2m03s` or `+1h05m`. Negative if records are out of order.
+fn delta(delta: TimeDelta) -> String {
+    let sign = if delta < TimeDelta::zero() { '-' } else { '+' };
+    let delta = delta.abs();
+    let seconds = delta.num_seconds();
+    if seconds < 60 {
+        format!(
+            "{}{}.{:03}s",
+            sign,
+            seconds,
+            delta.subsec_nanos() / 1_000_000
+        )
+    } else if seconds < 60 * 60 {
+        format!("{}{}m{:02}s", sign, seconds / 60, seconds % 60)
+    } else {
+        format!("{}{}h{:02}m", sign, seconds / (60 * 60), seconds / 60 % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bucket, delta, parse, parse_duration, relative, BucketRange, Mode, TimeDisplay,
+        TimeOptions, Zone, MAX_BUCKETS,
+    };
+    use chrono::TimeDelta;
+
+    #[test]
+    fn time_display() {
+        let time = parse("2024-03-31T00:30:00Z", "rfc3339").unwrap();
+        let options = TimeOptions {
+            format: Some("%Y-%m-%d %H:%M %Z".to_string()),
+            zone: Zone::parse("Europe/Berlin").unwrap(),
+            mode: Mode::Absolute,
+        };
+        let previous = parse("2024-03-30T23:00:00Z", "rfc3339");
+        let display = TimeDisplay::new(&options, time, previous);
+        assert_eq!("2024-03-31 01:30 +01:00", display.format(None).unwrap());
+        assert_eq!("01", display.format(Some("%H")).unwrap());
+
+        let options = TimeOptions {
+            mode: Mode::Delta,
+            ..options
+        };
+        let format = |previous| TimeDisplay::new(&options, time, previous).format(None);
+        assert_eq!("+0.000s", format(None).unwrap());
+        assert_eq!("+1h30m", format(previous).unwrap());
+        let options = TimeOptions::default();
+        assert_eq!(None, TimeDisplay::new(&options, time, None).format(None));
+
+        assert_eq!("-0.250s", delta(TimeDelta::milliseconds(-250)));
+        assert_eq!("+2m03s", delta(TimeDelta::seconds(123)));
+        assert_eq!("3m ago", relative(TimeDelta::seconds(200)));
+        assert_eq!("in 2d", relative(TimeDelta::days(-2)));
+        assert!(Zone::parse("Mars/Olympus").is_err());
+
+        assert_eq!(TimeDelta::minutes(5), parse_duration("5m").unwrap());
+        assert_eq!(
+            TimeDelta::milliseconds(500),
+            parse_duration("500ms").unwrap()
+        );
+        for invalid in ["5", "m", "0s", "5w"] {
+            assert!(parse_duration(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn bucket_range() {
+        let minute = TimeDelta::minutes(1);
+        let range = BucketRange::new(10, 12, minute);
+        assert_eq!((minute, 10..=12), (range.interval, range.indexes()));
+
+        // A year of minutes, e.g. from a record with a wrong date, takes wider buckets.
+        let first = parse("2023-01-01T10:00:00Z", "rfc3339").unwrap();
+        let last = parse("2024-01-01T10:00:00Z", "rfc3339").unwrap();
+        let range = BucketRange::new(bucket(first, minute), bucket(last, minute), minute);
+        assert_eq!(TimeDelta::minutes(526), range.interval);
+        assert!(range.indexes().count() <= MAX_BUCKETS as usize);
+        assert_eq!(range.indexes().start(), &range.widen(bucket(first, minute)));
+    }
+}